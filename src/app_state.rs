@@ -1,27 +1,46 @@
-use std::collections::HashMap;
-use std::ffi::OsStr;
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Instant;
 
 use druid::commands::SHOW_OPEN_PANEL;
 use druid::{
-    Application, ClipboardFormat, Command, Data, ExtEventSink, FileDialogOptions, FileSpec,
-    SingleUse, Target, WindowId,
+    Application, ClipboardFormat, Command, Data, ExtEventSink, FileDialogOptions, FileSpec, Point,
+    SingleUse, Size, Target, WindowConfig, WindowHandle, WindowId, WindowState,
 };
-use image::{DynamicImage, ImageOutputFormat};
-use preferences::{AppInfo, Preferences, PreferencesMap};
+use image::{DynamicImage, ImageFormat, ImageOutputFormat};
 
-use crate::commands::RECENTER_IMAGE;
+use crate::archive::{is_archive_path, list_archive_images, natural_cmp, split_archive_path};
+use crate::cli::{Cli, Filter};
+use crate::color_management::ColorManagement;
+use crate::commands::{
+    HISTOGRAM_COMPLETE, MONITOR_PROFILE_SELECTED, PROOFING_PROFILE_SELECTED, RAW_DEVELOP_FAILURE,
+    REALSIZE_IMAGE, RECENTER_IMAGE, SCALE_IMAGE,
+};
+use crate::histogram::Histogram;
+use crate::image_cache::ImageCache;
 use crate::image_container::*;
-use crate::types::{Direction, DisplayState, NewImageContainer};
+use crate::image_loader::{
+    is_supported_extension, load_image_file, DecodedImage, ImagePage, TextureInfo,
+    FILE_DIALOG_EXTENSIONS,
+};
+use crate::key_bindings::{KeyAction, KeyBindings};
+use crate::playlist::LaunchPaths;
+use crate::raw_image::develop_raw;
+use crate::remote_control;
+use crate::settings::Settings;
+use crate::single_instance::{self, ForwardedLaunch};
+use crate::types::{
+    CanvasBackground, ChannelView, Direction, DisplayState, NewImageContainer, RenderSettings,
+    SortMode, ThemeMode, ToneMapping, ZoomMode,
+};
+use crate::ui_builder::{drag_file_out, is_drag_out_supported};
 use crate::{IMAGE_LOAD_FAILURE, IMAGE_LOAD_SUCCESS, IMAGE_ROTATION_COMPLETE, REDRAW_IMAGE};
 
-const APP_SIG: AppInfo = AppInfo {
-    name: env!("CARGO_PKG_NAME"),
-    author: env!("CARGO_PKG_AUTHORS"),
-};
+// How many recently opened images and folders are remembered
+const RECENT_PATHS_LIMIT: usize = 10;
 
 #[derive(Clone, Data)]
 pub struct AppState {
@@ -33,13 +52,67 @@ pub struct AppState {
     command_queue: Arc<Mutex<Vec<Command>>>,
     loading_new_image: Arc<Mutex<bool>>,
     rotating_image: Arc<Mutex<bool>>,
+    /// The current image was rotated in memory, so it no longer matches its file
+    #[data(ignore)]
+    image_rotated: bool,
     current_image_index: usize,
     current_image_name: String,
     image_list: Arc<Mutex<Vec<PathBuf>>>,
     druid_event_sink: Arc<Mutex<ExtEventSink>>,
     pub dark_theme_enabled: bool,
+    theme_mode: ThemeMode,
+    system_dark_theme: bool,
     blur_enabled: bool,
     filtering_enabled: bool,
+    pixel_grid_enabled: bool,
+    tile_grid_size: Option<u32>,
+    canvas_background: CanvasBackground,
+    channel_view: ChannelView,
+    histogram_enabled: bool,
+    exposure: f64,
+    gamma: f64,
+    tone_mapping: ToneMapping,
+    color_management: ColorManagement,
+    recursive_browsing_enabled: bool,
+    folder_depth_limit: Option<usize>,
+    skip_hidden_folders: bool,
+    #[data(ignore)]
+    browse_root: Option<PathBuf>,
+    sort_mode: SortMode,
+    zoom_mode: ZoomMode,
+    fullscreen_enabled: bool,
+    slideshow_interval: Option<f64>,
+    /// Seconds between images when a slideshow is started from the keyboard
+    slideshow_delay: f64,
+    single_instance_enabled: bool,
+    prefetch_count: usize,
+    cache_memory_limit_mb: usize,
+    wrap_around_enabled: bool,
+    confirm_delete_enabled: bool,
+    key_bindings: KeyBindings,
+    restore_session_enabled: bool,
+    recent_paths: Arc<Vec<PathBuf>>,
+    /// The image that was showing when the viewer was last closed
+    #[data(ignore)]
+    last_image: Option<PathBuf>,
+    /// Size and position of the window when it isn't maximized, once known
+    #[data(ignore)]
+    window_size: Option<Size>,
+    #[data(ignore)]
+    window_position: Option<Point>,
+    #[data(ignore)]
+    window_maximized: bool,
+    #[data(ignore)]
+    image_cache: Arc<Mutex<ImageCache>>,
+    /// Bumped on every image load, so prefetching for an image that's been left behind stops
+    #[data(ignore)]
+    prefetch_generation: Arc<AtomicUsize>,
+    /// Preferences overridden from the command line, which aren't saved unless changed again
+    #[data(ignore)]
+    session_overrides: HashSet<&'static str>,
+    /// The settings file couldn't be loaded, and is left alone rather than overwritten
+    #[data(ignore)]
+    settings_save_disabled: bool,
 }
 
 impl AppState {
@@ -47,7 +120,7 @@ impl AppState {
         self.window_id = Some(id);
     }
 
-    pub fn from(dark_theme_enabled: bool, event_sink: ExtEventSink) -> Self {
+    pub fn from(system_dark_theme: bool, event_sink: ExtEventSink) -> Self {
         Self {
             window_id: None,
             current_image: Arc::new(Mutex::new(ImageState::Empty)),
@@ -55,13 +128,50 @@ impl AppState {
             command_queue: Arc::new(Mutex::new(vec![])),
             loading_new_image: Arc::new(Mutex::new(false)),
             rotating_image: Arc::new(Mutex::new(false)),
+            image_rotated: false,
             current_image_index: 0,
             current_image_name: String::new(),
             image_list: Arc::new(Mutex::new(Vec::new())),
             druid_event_sink: Arc::new(Mutex::new(event_sink)),
-            dark_theme_enabled,
+            dark_theme_enabled: system_dark_theme,
+            theme_mode: ThemeMode::System,
+            system_dark_theme,
             blur_enabled: true,
             filtering_enabled: false,
+            pixel_grid_enabled: false,
+            tile_grid_size: None,
+            canvas_background: CanvasBackground::Checkerboard,
+            channel_view: ChannelView::Composite,
+            histogram_enabled: false,
+            exposure: 0.,
+            gamma: 2.2,
+            tone_mapping: ToneMapping::Clamp,
+            color_management: ColorManagement::new(),
+            recursive_browsing_enabled: false,
+            folder_depth_limit: Some(3),
+            skip_hidden_folders: true,
+            browse_root: None,
+            sort_mode: SortMode::Name,
+            zoom_mode: ZoomMode::Fit,
+            fullscreen_enabled: false,
+            slideshow_interval: None,
+            slideshow_delay: 5.,
+            single_instance_enabled: false,
+            prefetch_count: 2,
+            cache_memory_limit_mb: 512,
+            wrap_around_enabled: true,
+            confirm_delete_enabled: true,
+            key_bindings: KeyBindings::new(),
+            restore_session_enabled: true,
+            recent_paths: Arc::new(Vec::new()),
+            last_image: None,
+            window_size: None,
+            window_position: None,
+            window_maximized: false,
+            image_cache: Arc::new(Mutex::new(ImageCache::new(512 * 1024 * 1024))),
+            prefetch_generation: Arc::new(AtomicUsize::new(0)),
+            session_overrides: HashSet::new(),
+            settings_save_disabled: false,
         }
     }
 
@@ -105,113 +215,352 @@ impl AppState {
         image_list.len()
     }
 
-    pub fn startup(&mut self, path: String) {
+    /// Opens a file, folder or archive, starting at the given index into its image list if
+    /// there is one, so that only that image gets loaded
+    pub fn startup(&mut self, path: String, start_index: Option<usize>) {
         let current_time = Instant::now();
         let file_path_result = Path::new(&path).canonicalize();
         if let Ok(file_path) = file_path_result {
-            if file_path.is_file() {
-                self.set_loading_state(true);
-                self.load_image(&file_path, &current_time);
+            self.add_recent_path(&file_path);
+            let start_image = if is_archive_path(&file_path) {
+                self.parse_archive(&file_path);
+                self.select_start_image(start_index)
+            } else if file_path.is_file() {
+                // The folder is listed first so the images after this one can be prefetched
                 self.parse_folder(&file_path);
+                // A file missing from its folder's list, like one with an unusual extension,
+                // is still shown when opened directly
+                match start_index {
+                    Some(_) => self.select_start_image(start_index),
+                    None => Some(file_path),
+                }
             } else if file_path.is_dir() {
                 self.parse_folder(&file_path);
-
-                let first_image: PathBuf;
-                {
-                    let image_list_guard = &self.image_list;
-                    let image_list = image_list_guard.lock().unwrap();
-                    first_image = image_list[0].clone();
-                }
-                self.load_image(&first_image, &current_time);
+                self.select_start_image(start_index)
+            } else {
+                None
+            };
+            if let Some(start_image) = start_image {
+                self.set_loading_state(true);
+                self.load_image(&start_image, &current_time);
             }
         } else {
             self.set_loading_state(false);
         }
     }
 
-    fn parse_folder(&mut self, path: &Path) {
-        let path_anchor = path.to_path_buf();
-
-        let supported_filetypes = [
-            OsStr::new("png"),
-            OsStr::new("jpg"),
-            OsStr::new("jpeg"),
-            OsStr::new("bmp"),
-            OsStr::new("ico"),
-            OsStr::new("tiff"),
-            OsStr::new("webp"),
-            OsStr::new("avif"),
-            OsStr::new("pnm"),
-            OsStr::new("dds"),
-            OsStr::new("tga"),
-            OsStr::new("exr"),
-        ];
-        let is_file_supported = |file_extension: &OsStr| {
-            for extension in supported_filetypes {
-                if file_extension.to_ascii_lowercase() == extension {
-                    return true;
-                }
-            }
+    /// Moves to the start index if it's in the image list, and returns the image to show first
+    fn select_start_image(&mut self, start_index: Option<usize>) -> Option<PathBuf> {
+        let image_list = self.image_list.lock().unwrap();
+        if let Some(start_index) = start_index.filter(|index| *index < image_list.len()) {
+            self.current_image_index = start_index;
+        }
+        image_list.get(self.current_image_index).cloned()
+    }
 
-            false
+    /// Uses an explicit set of files, folders and archives as the image list instead of
+    /// scanning the folder of the opened file
+    pub fn startup_with_list(&mut self, paths: Vec<PathBuf>, start_index: Option<usize>) {
+        let depth_limit = if self.recursive_browsing_enabled {
+            self.folder_depth_limit
+        } else {
+            Some(0)
         };
-
         let mut files: Vec<PathBuf> = Vec::new();
-        let mut current_index: usize = 0;
-        let current_file_name = path_anchor.file_name();
-        let current_folder = path_anchor.parent().unwrap();
-
-        for entry in current_folder
-            .read_dir()
-            .expect("read_dir call failed")
-            .flatten()
-        {
-            if let Some(file_extension) = entry.path().extension() {
-                if is_file_supported(file_extension) {
-                    files.push(entry.path());
+        for path in paths {
+            let path = match path.canonicalize() {
+                Ok(path) => path,
+                Err(error) => {
+                    println!("Skipping {}: {}", path.display(), error);
+                    continue;
                 }
+            };
+            if is_archive_path(&path) {
+                files.extend(list_archive_images(&path).unwrap_or_default());
+            } else if path.is_dir() {
+                let mut folder_files = Vec::new();
+                collect_folder_images(
+                    &path,
+                    depth_limit,
+                    self.skip_hidden_folders,
+                    &mut folder_files,
+                );
+                sort_files(&mut folder_files, self.sort_mode);
+                files.extend(folder_files);
+            } else if path.extension().map_or(false, is_supported_extension) {
+                files.push(path);
             }
         }
 
-        files.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+        // The same image may be named more than once, directly and through a folder or list
+        let mut seen = HashSet::new();
+        files.retain(|file| seen.insert(file.clone()));
 
-        // Find & save index of the initial file
-        if let Some(file_name) = current_file_name {
-            for (index, entry) in files.iter().enumerate() {
-                if let Some(entry_file_name) = entry.file_name() {
-                    if entry_file_name == file_name {
-                        current_index = index;
-                        break;
-                    }
-                }
+        self.browse_root = None;
+        self.set_image_list(0, files);
+        if let Some(start_image) = self.select_start_image(start_index) {
+            self.set_loading_state(true);
+            self.load_image(&start_image, &Instant::now());
+        }
+    }
+
+    /// Reopens the image that was showing when the viewer was last closed, or its folder if
+    /// the image has since gone
+    pub fn restore_last_session(&mut self) {
+        if !self.restore_session_enabled {
+            return;
+        }
+        if let Some(path) = self.last_image.clone() {
+            if path.exists() {
+                self.startup(path.to_string_lossy().to_string(), None);
+            } else if let Some(folder) = path.parent().filter(|folder| folder.is_dir()) {
+                self.startup(folder.to_string_lossy().to_string(), None);
             }
         }
+    }
+
+    /// The file on disk to reopen next time, which is the archive for archive entries
+    fn get_last_image(&self) -> Option<PathBuf> {
+        if self.has_image() && self.get_image_list_size() > 0 {
+            let image_list_guard = &self.image_list;
+            let image_list = image_list_guard.lock().unwrap();
+            Some(self.get_current_file_path(&image_list))
+        } else {
+            None
+        }
+    }
+
+    pub fn get_recent_paths(&self) -> &[PathBuf] {
+        &self.recent_paths
+    }
+
+    fn add_recent_path(&mut self, path: &Path) {
+        let recent_paths = Arc::make_mut(&mut self.recent_paths);
+        recent_paths.retain(|recent_path| recent_path != path);
+        recent_paths.insert(0, path.to_path_buf());
+        recent_paths.truncate(RECENT_PATHS_LIMIT);
+    }
+
+    /// Drops images and folders that were moved or deleted since they were opened
+    pub fn prune_recent_paths(&mut self) {
+        if self.recent_paths.iter().any(|path| !path.exists()) {
+            Arc::make_mut(&mut self.recent_paths).retain(|path| path.exists());
+        }
+    }
+
+    pub fn open_recent_path(&mut self, path: &Path) {
+        if path.exists() {
+            self.startup(path.to_string_lossy().to_string(), None);
+        } else {
+            self.prune_recent_paths();
+        }
+    }
+
+    pub fn clear_recent_paths(&mut self) {
+        self.recent_paths = Arc::new(Vec::new());
+    }
+
+    pub fn restore_session_enabled(&self) -> bool {
+        self.restore_session_enabled
+    }
+
+    pub fn restore_session_enable_toggle(&mut self) {
+        self.restore_session_enabled ^= true;
+    }
+
+    /// Opens whatever was given on launch, optionally starting further into the image list
+    pub fn open_launch_paths(&mut self, launch_paths: LaunchPaths, start_index: Option<usize>) {
+        match launch_paths {
+            LaunchPaths::Single(file_name) => self.startup(file_name, start_index),
+            LaunchPaths::List(paths) => self.startup_with_list(paths, start_index),
+            LaunchPaths::None => {}
+        }
+    }
+
+    /// Opens a launch handed over by another process, the same way as the first launch
+    pub fn open_forwarded_launch(&mut self, forwarded_launch: ForwardedLaunch) {
+        let args =
+            std::iter::once(env!("CARGO_PKG_NAME").to_string()).chain(forwarded_launch.option_args);
+        let mut start_index = None;
+        match Cli::try_parse_from(args) {
+            Ok(cli) => {
+                self.apply_session_overrides(&cli);
+                start_index = cli.start_index;
+            }
+            Err(error) => println!("Ignoring forwarded options: {}", error),
+        }
+        self.open_launch_paths(forwarded_launch.launch_paths, start_index);
+    }
+
+    /// Builds the image list from the entries of an archive, which then browses like a folder
+    fn parse_archive(&mut self, path: &Path) {
+        self.browse_root = None;
+        match list_archive_images(path) {
+            Ok(entries) => self.set_image_list(0, entries),
+            Err(error) => {
+                println!("Failed to read archive: {}", error);
+                // Nothing from the previous folder should be shown as if it came from here
+                self.set_image_list(0, Vec::new());
+                self.set_loading_state(false);
+                self.image_load_failure(&path.to_path_buf());
+            }
+        }
+    }
+
+    fn parse_folder(&mut self, path: &Path) {
+        let folder = if path.is_dir() {
+            path.to_path_buf()
+        } else {
+            path.parent().unwrap().to_path_buf()
+        };
+        self.scan_folder(&folder, path);
+    }
+
+    /// Lists the images in a folder, and in its subfolders if recursive browsing is on, then
+    /// selects the given file
+    fn scan_folder(&mut self, folder: &Path, current_file: &Path) {
+        let depth_limit = if self.recursive_browsing_enabled {
+            self.folder_depth_limit
+        } else {
+            Some(0)
+        };
+        let mut files: Vec<PathBuf> = Vec::new();
+        collect_folder_images(folder, depth_limit, self.skip_hidden_folders, &mut files);
+
+        sort_files(&mut files, self.sort_mode);
+
+        // Find & save index of the initial file
+        let current_index = files
+            .iter()
+            .position(|entry| entry == current_file)
+            .unwrap_or(0);
 
         // Set the image index and file list
+        self.browse_root = Some(folder.to_path_buf());
         self.set_image_list(current_index, files);
     }
 
+    /// Rebuilds the image list after the folder browsing options change
+    pub fn rescan_folder(&mut self) {
+        let current_file: PathBuf;
+        {
+            let image_list_guard = &self.image_list;
+            let image_list = image_list_guard.lock().unwrap();
+            match image_list.get(self.current_image_index) {
+                Some(path) if split_archive_path(path).is_none() => current_file = path.clone(),
+                _ => return,
+            }
+        }
+        if let Some(root) = self.browse_root.clone() {
+            self.scan_folder(&root, &current_file);
+        }
+    }
+
     fn load_image(&mut self, image_path: &Path, request_timestamp: &Instant) {
         let event_sink_mutex_ref = self.druid_event_sink.clone();
+        let image_cache = self.image_cache.clone();
+        let prefetch_generation = self.prefetch_generation.clone();
+        let generation = prefetch_generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let prefetch_paths = self.get_prefetch_paths();
         let path_anchor = image_path.to_path_buf();
         let request_timestamp = *request_timestamp;
         thread::spawn(move || {
-            let image_result = image::open(&path_anchor);
+            let cached_image = image_cache.lock().unwrap().get(&path_anchor);
+            let image_result = match cached_image {
+                Some(image) => Ok(image),
+                None => load_image_file(&path_anchor).map(|image| {
+                    image_cache
+                        .lock()
+                        .unwrap()
+                        .insert(&path_anchor, image.clone());
+                    image
+                }),
+            };
+            {
+                let event_sink_mutex = event_sink_mutex_ref.lock().unwrap();
+                let event_sink = &*event_sink_mutex;
+                if let Ok(image) = image_result {
+                    let pth = path_anchor.to_str().unwrap().to_string();
+                    let wrapper = NewImageContainer::from(pth, request_timestamp, image);
+                    event_sink
+                        .submit_command(IMAGE_LOAD_SUCCESS, SingleUse::new(wrapper), Target::Auto)
+                        .expect("Failed to send new image loaded command");
+                } else {
+                    event_sink
+                        .submit_command(IMAGE_LOAD_FAILURE, path_anchor, Target::Auto)
+                        .expect("Failed to submit image loading failure notification command");
+                }
+            }
+
+            for path in prefetch_paths {
+                if prefetch_generation.load(Ordering::SeqCst) != generation {
+                    break;
+                }
+                if image_cache.lock().unwrap().contains(&path) {
+                    continue;
+                }
+                if let Ok(image) = load_image_file(&path) {
+                    image_cache.lock().unwrap().insert(&path, image);
+                }
+            }
+        });
+    }
+
+    /// The images that follow the current one, in browsing order
+    fn get_prefetch_paths(&self) -> Vec<PathBuf> {
+        let image_list = self.image_list.lock().unwrap();
+        let image_count = image_list.len();
+        (1..=self.prefetch_count.min(image_count.saturating_sub(1)))
+            .map(|offset| self.current_image_index + offset)
+            .filter_map(|index| match index < image_count {
+                true => Some(index),
+                false if self.wrap_around_enabled => Some(index - image_count),
+                false => None,
+            })
+            .map(|index| image_list[index].clone())
+            .collect()
+    }
+
+    /// Replaces the embedded preview of a RAW file with a full development of its sensor data
+    pub fn develop_raw_image(&mut self, request_timestamp: &Instant) {
+        {
+            let image_state_guard = self.get_image_ref();
+            let image_state = image_state_guard.lock().unwrap();
+            match &*image_state {
+                ImageState::Loaded(image) if image.is_raw_preview() => {}
+                _ => return,
+            }
+        }
+        self.set_loading_state(true);
+
+        let event_sink_mutex_ref = self.druid_event_sink.clone();
+        let image_list_guard = &self.image_list;
+        let image_list = image_list_guard.lock().unwrap();
+        let path_anchor = image_list[self.current_image_index].clone();
+        let request_timestamp = *request_timestamp;
+        thread::spawn(move || {
+            let develop_result = develop_raw(&path_anchor);
             let event_sink_mutex = event_sink_mutex_ref.lock().unwrap();
             let event_sink = &*event_sink_mutex;
-            if let Ok(image) = image_result {
-                let pth = path_anchor.to_str().unwrap().to_string();
-                let wrapper = NewImageContainer::from(pth, request_timestamp, image);
-                event_sink
-                    .submit_command(IMAGE_LOAD_SUCCESS, SingleUse::new(wrapper), Target::Auto)
-                    .expect("Failed to send new image loaded command");
-            } else {
-                event_sink
-                    .submit_command(IMAGE_LOAD_FAILURE, path_anchor, Target::Auto)
-                    .expect("Failed to submit image loading failure notification command");
+            match develop_result {
+                Ok(image) => {
+                    let pth = path_anchor.to_str().unwrap().to_string();
+                    let wrapper = NewImageContainer::from(pth, request_timestamp, image);
+                    event_sink
+                        .submit_command(IMAGE_LOAD_SUCCESS, SingleUse::new(wrapper), Target::Auto)
+                        .expect("Failed to send new image loaded command");
+                }
+                Err(error) => {
+                    // Keep showing the preview rather than replacing it with an error
+                    println!("Failed to develop RAW image: {}", error);
+                    event_sink
+                        .submit_command(RAW_DEVELOP_FAILURE, (), Target::Auto)
+                        .expect("Failed to send RAW develop failure command");
+                }
             }
         });
     }
+
     pub fn set_current_image(&mut self, container_wrapper: Option<NewImageContainer>) {
         if let Some(wrapper) = container_wrapper {
             let mut loaded_new_image: bool = false;
@@ -232,18 +581,56 @@ impl AppState {
             }
 
             if loaded_new_image {
-                let image_name = Path::new(&wrapper.path)
-                    .file_name()
-                    .unwrap()
-                    .to_os_string()
-                    .into_string()
-                    .unwrap();
+                self.image_rotated = false;
+                let image_path = Path::new(&wrapper.path);
+                let image_name = match split_archive_path(image_path) {
+                    Some((archive_path, _entry_name)) => format!(
+                        "{} › {}",
+                        archive_path.file_name().unwrap().to_string_lossy(),
+                        image_path.file_name().unwrap().to_string_lossy()
+                    ),
+                    None => {
+                        let file_name = image_path
+                            .file_name()
+                            .unwrap()
+                            .to_os_string()
+                            .into_string()
+                            .unwrap();
+                        // Images found while browsing recursively show which subfolder they're in
+                        let subfolder = self.browse_root.as_ref().and_then(|root| {
+                            image_path
+                                .parent()?
+                                .strip_prefix(root)
+                                .ok()
+                                .filter(|subfolder| !subfolder.as_os_str().is_empty())
+                        });
+                        match subfolder {
+                            Some(subfolder) => {
+                                format!("{} › {}", subfolder.to_string_lossy(), file_name)
+                            }
+                            None => file_name,
+                        }
+                    }
+                };
                 self.set_current_image_name(image_name);
+                remote_control::publish_event("image_changed", &wrapper.path);
+                if self.histogram_enabled {
+                    self.compute_histogram();
+                }
                 // self.set_display_state(DisplayState::Centered(true));
                 let event_sink = self.druid_event_sink.lock().unwrap();
-                event_sink
-                    .submit_command(RECENTER_IMAGE, Instant::now(), Target::Auto)
-                    .expect("Failed to send command");
+                match self.zoom_mode {
+                    ZoomMode::Fit => {
+                        event_sink.submit_command(RECENTER_IMAGE, Instant::now(), Target::Auto)
+                    }
+                    ZoomMode::RealSize => {
+                        event_sink.submit_command(REALSIZE_IMAGE, Instant::now(), Target::Auto)
+                    }
+                    ZoomMode::Scale(scale) => {
+                        event_sink.submit_command(SCALE_IMAGE, scale, Target::Auto)
+                    }
+                }
+                .expect("Failed to send command");
             }
         }
     }
@@ -261,6 +648,7 @@ impl AppState {
             .into_string()
             .unwrap();
         self.set_current_image_name(image_name);
+        remote_control::publish_event("image_load_failed", &image_path.to_string_lossy());
         // self.get_display_state_mut().set();
     }
     pub fn get_image_ref(&self) -> Arc<Mutex<ImageState>> {
@@ -271,8 +659,12 @@ impl AppState {
     }
     pub fn load_next_image(&mut self, request_timestamp: &Instant) {
         if self.get_image_list_size() > 0 {
+            let is_last_image = self.current_image_index >= self.get_image_list_size() - 1;
+            if is_last_image && !self.wrap_around_enabled {
+                return;
+            }
             self.set_loading_state(true);
-            if self.current_image_index >= self.get_image_list_size() - 1 {
+            if is_last_image {
                 self.current_image_index = 0;
             } else {
                 self.current_image_index += 1;
@@ -288,6 +680,9 @@ impl AppState {
     }
     pub fn load_prev_image(&mut self, request_timestamp: &Instant) {
         if self.get_image_list_size() > 0 {
+            if self.current_image_index == 0 && !self.wrap_around_enabled {
+                return;
+            }
             self.set_loading_state(true);
             if self.current_image_index == 0 {
                 self.current_image_index = self.get_image_list_size() - 1;
@@ -304,6 +699,144 @@ impl AppState {
         }
     }
 
+    pub fn show_image_at(&mut self, index: usize, request_timestamp: &Instant) {
+        let image_path: PathBuf;
+        {
+            let image_list_guard = &self.image_list;
+            let image_list = image_list_guard.lock().unwrap();
+            match image_list.get(index) {
+                Some(path) => image_path = path.clone(),
+                None => return,
+            }
+        }
+        self.current_image_index = index;
+        self.set_loading_state(true);
+        self.load_image(&image_path, request_timestamp);
+    }
+
+    pub fn step_page(&mut self, step: isize) {
+        let changed_page: bool;
+        {
+            let image_state_guard = self.get_image_ref();
+            let mut image_state = image_state_guard.lock().unwrap();
+            if let ImageState::Loaded(image) = &mut *image_state {
+                // The cross layout shows a whole cube, so paging moves between array slices
+                let step = if image.get_cube_cross_layout() {
+                    step * 6
+                } else {
+                    step
+                };
+                let page_count = image.get_page_count() as isize;
+                let new_index = image.get_page_index() as isize + step;
+                changed_page = new_index >= 0 && new_index < page_count;
+                if changed_page {
+                    image.set_page(new_index as usize);
+                }
+            } else {
+                changed_page = false;
+            }
+        }
+
+        if changed_page {
+            self.sub_image_changed();
+        }
+    }
+
+    pub fn step_mip_level(&mut self, step: isize) {
+        let changed_level: bool;
+        {
+            let image_state_guard = self.get_image_ref();
+            let mut image_state = image_state_guard.lock().unwrap();
+            if let ImageState::Loaded(image) = &mut *image_state {
+                let level_count = image.get_mip_level_count() as isize;
+                let new_level = image.get_mip_level() as isize + step;
+                changed_level = new_level >= 0 && new_level < level_count;
+                if changed_level {
+                    image.set_mip_level(new_level as usize);
+                }
+            } else {
+                changed_level = false;
+            }
+        }
+
+        if changed_level {
+            self.sub_image_changed();
+        }
+    }
+
+    pub fn toggle_cube_cross_layout(&mut self) {
+        let toggled: bool;
+        {
+            let image_state_guard = self.get_image_ref();
+            let mut image_state = image_state_guard.lock().unwrap();
+            if let ImageState::Loaded(image) = &mut *image_state {
+                toggled = image.is_cube_map();
+                if toggled {
+                    image.set_cube_cross_layout(!image.get_cube_cross_layout());
+                }
+            } else {
+                toggled = false;
+            }
+        }
+
+        if toggled {
+            self.sub_image_changed();
+        }
+    }
+
+    fn sub_image_changed(&mut self) {
+        if self.histogram_enabled {
+            self.compute_histogram();
+        }
+        let event_sink = self.druid_event_sink.lock().unwrap();
+        event_sink
+            .submit_command(RECENTER_IMAGE, Instant::now(), Target::Auto)
+            .expect("Failed to send command");
+    }
+
+    /// A short "page 2/7" style description of the current sub-image, if the file has several,
+    /// followed by the mip level and pixel format for textures
+    pub fn get_page_description(&self) -> Option<String> {
+        let image_state_guard = self.get_image_ref();
+        let image_state = image_state_guard.lock().unwrap();
+        if let ImageState::Loaded(image) = &*image_state {
+            let mut parts = Vec::new();
+            if image.get_cube_cross_layout() {
+                let slice_count = image.get_page_count() / 6;
+                if slice_count > 1 {
+                    let slice = image.get_page_index() / 6;
+                    parts.push(format!("Slice {}/{}", slice + 1, slice_count));
+                }
+                parts.push("Cube cross".to_string());
+            } else if image.get_page_count() > 1 {
+                parts.push(format!(
+                    "Page {}/{}",
+                    image.get_page_index() + 1,
+                    image.get_page_count()
+                ));
+                if let Some(label) = image.get_page_label() {
+                    parts.push(label.clone());
+                }
+            }
+            if image.get_mip_level_count() > 1 {
+                let size = image.get_size();
+                parts.push(format!(
+                    "Mip {} ({} × {})",
+                    image.get_mip_level(),
+                    size.width,
+                    size.height
+                ));
+            }
+            if let Some(texture_info) = image.get_texture_info() {
+                parts.push(texture_info.format_name.clone());
+            }
+            if !parts.is_empty() {
+                return Some(parts.join(" · "));
+            }
+        }
+        None
+    }
+
     pub fn get_image_name(&self) -> String {
         self.current_image_name.clone()
     }
@@ -335,12 +868,20 @@ impl AppState {
         if self.get_image_list_size() == 0 {
             return;
         }
-        let current_image: DynamicImage;
+        let current_pages: Vec<ImagePage>;
+        let current_page_index: usize;
+        let current_texture_info: Option<TextureInfo>;
+        let current_icc_profile: Option<Vec<u8>>;
+        let current_raw_preview: bool;
         {
             let image_state_guard = self.get_image_ref();
             let image_state = image_state_guard.lock().unwrap();
             if let ImageState::Loaded(image) = &*image_state {
-                current_image = image.get_image().clone();
+                current_pages = image.get_pages().clone();
+                current_page_index = image.get_page_index();
+                current_texture_info = image.get_texture_info().cloned();
+                current_icc_profile = image.get_icc_profile().cloned();
+                current_raw_preview = image.is_raw_preview();
             } else {
                 return;
             }
@@ -361,9 +902,24 @@ impl AppState {
         thread::spawn(move || {
             let event_sink_mutex = event_sink_mutex_ref.lock().unwrap();
             let event_sink = &*event_sink_mutex;
-            let rotated_image = match direction {
-                Direction::Left => current_image.rotate270(),
-                Direction::Right => current_image.rotate90(),
+            // Every sub-image is rotated so that paging keeps a consistent orientation
+            let rotate = |image: DynamicImage| direction.rotate(image);
+            let rotated_pages = current_pages
+                .into_iter()
+                .map(|page| ImagePage {
+                    image: rotate(page.image),
+                    label: page.label,
+                    mip_levels: page.mip_levels.into_iter().map(&rotate).collect(),
+                })
+                .collect();
+            let rotated_image = DecodedImage {
+                pages: rotated_pages,
+                page_index: current_page_index,
+                texture_info: current_texture_info,
+                icc_profile: current_icc_profile,
+                // Rotated vector images fall back to their bitmap rendition
+                vector_source: None,
+                raw_preview: current_raw_preview,
             };
             let pth = path_anchor.to_str().unwrap().to_string();
             let wrapper = NewImageContainer::from(pth, timestamp, rotated_image);
@@ -396,6 +952,70 @@ impl AppState {
         }
     }
 
+    /// Starts dragging the current image out to other applications. The file itself is
+    /// dragged where possible, while rotated images and archive entries are written to a
+    /// temporary PNG first.
+    pub fn start_image_drag(&self) {
+        // Checked first, so a rotated image isn't encoded for a drag that can't happen
+        if !is_drag_out_supported() {
+            println!("Dragging images out isn't supported on this platform");
+            return;
+        }
+        let path = {
+            let image_list = self.image_list.lock().unwrap();
+            match image_list.get(self.current_image_index) {
+                Some(path) => path.clone(),
+                None => return,
+            }
+        };
+        if !self.image_rotated && path.is_file() {
+            drag_file_out(&path);
+            return;
+        }
+
+        // Each process gets its own folder, so other instances can't replace the file mid-drag
+        let drag_folder = std::env::temp_dir().join(format!("foxfire-drag-{}", std::process::id()));
+        let file_stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let png_path = drag_folder.join(format!("{}.png", file_stem));
+        // Written out before dragging, as the drag blocks until the drop
+        let result = {
+            let image_state_guard = self.get_image_ref();
+            let image_state = image_state_guard.lock().unwrap();
+            match &*image_state {
+                ImageState::Loaded(image) => {
+                    // PNG has no floating point pixels, so HDR images are brought down to 8 bits
+                    let png_image = match image.get_image() {
+                        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+                            DynamicImage::ImageRgba8(image.get_image().to_rgba8())
+                        }
+                        _ => image.get_image().clone(),
+                    };
+                    std::fs::create_dir_all(&drag_folder)
+                        .map_err(|e| e.to_string())
+                        .and_then(|()| {
+                            png_image
+                                .save_with_format(&png_path, ImageFormat::Png)
+                                .map_err(|e| e.to_string())
+                        })
+                }
+                _ => return,
+            }
+        };
+        match result {
+            // The drag only returns once it's dropped, so the file can go straight after
+            Ok(()) => {
+                drag_file_out(&png_path);
+                let _result = std::fs::remove_file(&png_path);
+                let _result = std::fs::remove_dir(&drag_folder);
+            }
+            Err(error) => println!("Failed to write {}: {}", png_path.display(), error),
+        }
+    }
+
+    pub fn set_image_rotated(&mut self) {
+        self.image_rotated = true;
+    }
+
     pub fn get_loading_state(&self) -> bool {
         let loading_state = self.loading_new_image.lock().unwrap();
         *loading_state
@@ -416,16 +1036,20 @@ impl AppState {
 
     pub fn show_file_load_dialog(&mut self) {
         if let Some(window_id) = self.window_id {
-            let common_image_files = FileSpec::new(
-                "Common image files",
+            let common_image_files = FileSpec::new("Common image files", FILE_DIALOG_EXTENSIONS);
+            let raw_files = FileSpec::new(
+                "Camera RAW files",
                 &[
-                    "png", "PNG", "jpg", "jpeg", "JPG", "JPEG", "bmp", "BMP", "ico", "ICO", "tiff",
-                    "TIFF", "webp", "WEBP", "avif", "AVIF", "pnm", "PNM", "dds", "DDS", "tga",
-                    "TGA", "exr", "EXR",
+                    "cr2", "CR2", "cr3", "CR3", "nef", "NEF", "arw", "ARW", "dng", "DNG", "raf",
+                    "RAF", "orf", "ORF",
                 ],
             );
+            let archives = FileSpec::new(
+                "Image archives",
+                &["zip", "ZIP", "cbz", "CBZ", "tar", "TAR", "cbt", "CBT"],
+            );
             let options = FileDialogOptions::new()
-                .allowed_types(vec![common_image_files])
+                .allowed_types(vec![common_image_files, raw_files, archives])
                 .name_label("Image")
                 .title("Choose an image to load")
                 .button_text("Load");
@@ -453,8 +1077,123 @@ impl AppState {
             .expect("Failed to send redraw command");
     }
 
-    pub fn show_fullscreen_slideshow(&mut self) {
-        todo!()
+    pub fn get_window_id(&self) -> Option<WindowId> {
+        self.window_id
+    }
+
+    pub fn fullscreen_enabled(&self) -> bool {
+        self.fullscreen_enabled
+    }
+
+    pub fn fullscreen_toggle(&mut self) {
+        self.fullscreen_enabled ^= true;
+    }
+
+    /// Fullscreen is a maximized window without a title bar
+    pub fn get_window_config(&self) -> WindowConfig {
+        let window_state = match self.fullscreen_enabled {
+            true => WindowState::Maximized,
+            false => WindowState::Restored,
+        };
+        WindowConfig::default()
+            .set_window_state(window_state)
+            .show_titlebar(!self.fullscreen_enabled)
+    }
+
+    pub fn has_window_geometry(&self) -> bool {
+        self.window_size.is_some()
+    }
+
+    /// Remembers where the window is, so the next run opens in the same place
+    pub fn record_window_geometry(&mut self, window: &WindowHandle) {
+        // Fullscreen is a maximized window itself, so leave the geometry from before it
+        if self.fullscreen_enabled {
+            return;
+        }
+        match window.get_window_state() {
+            WindowState::Maximized => self.window_maximized = true,
+            WindowState::Minimized => {}
+            WindowState::Restored => {
+                self.window_maximized = false;
+                self.window_size = Some(window.get_size());
+                self.window_position = Some(window.get_position());
+            }
+        }
+    }
+
+    pub fn slideshow_interval(&self) -> Option<f64> {
+        self.slideshow_interval
+    }
+
+    pub fn set_slideshow_interval(&mut self, interval: Option<f64>) {
+        self.slideshow_interval = interval;
+    }
+
+    pub fn slideshow_toggle(&mut self) {
+        self.slideshow_interval = match self.slideshow_interval {
+            Some(_) => None,
+            None => Some(self.slideshow_delay),
+        };
+    }
+
+    pub fn slideshow_delay(&self) -> f64 {
+        self.slideshow_delay
+    }
+
+    /// Also changes the pace of a slideshow that's already running
+    pub fn set_slideshow_delay(&mut self, delay: f64) {
+        self.slideshow_delay = delay;
+        if self.slideshow_interval.is_some() {
+            self.slideshow_interval = Some(delay);
+        }
+    }
+
+    pub fn prefetch_count(&self) -> usize {
+        self.prefetch_count
+    }
+
+    pub fn set_prefetch_count(&mut self, count: usize) {
+        self.prefetch_count = count;
+    }
+
+    pub fn cache_memory_limit_mb(&self) -> usize {
+        self.cache_memory_limit_mb
+    }
+
+    pub fn set_cache_memory_limit_mb(&mut self, limit: usize) {
+        self.cache_memory_limit_mb = limit;
+        self.image_cache
+            .lock()
+            .unwrap()
+            .set_memory_limit(limit.saturating_mul(1024 * 1024));
+    }
+
+    pub fn wrap_around_enabled(&self) -> bool {
+        self.wrap_around_enabled
+    }
+
+    pub fn wrap_around_enable_toggle(&mut self) {
+        self.wrap_around_enabled ^= true;
+    }
+
+    pub fn confirm_delete_enabled(&self) -> bool {
+        self.confirm_delete_enabled
+    }
+
+    pub fn confirm_delete_enable_toggle(&mut self) {
+        self.confirm_delete_enabled ^= true;
+    }
+
+    pub fn get_key_bindings(&self) -> &KeyBindings {
+        &self.key_bindings
+    }
+
+    pub fn get_key_binding(&self, action: KeyAction) -> String {
+        self.key_bindings.get(action)
+    }
+
+    pub fn set_key_binding(&mut self, action: KeyAction, binding: String) {
+        self.key_bindings.set(action, binding);
     }
 
     pub fn delete_image(&mut self) {
@@ -465,16 +1204,26 @@ impl AppState {
                 let image_list = image_list_guard.lock().unwrap();
                 path = image_list[self.current_image_index].to_path_buf();
             }
+            // Entries can't be removed from an archive, and trashing the archive itself would
+            // take every other image with it
+            if split_archive_path(&path).is_some() {
+                return;
+            }
             let result = trash::delete(path);
             if result.is_ok() {
                 if self.get_image_list_size() > 1 {
+                    let deleted_image_index = self.current_image_index;
                     {
-                        let deleted_image_index = self.current_image_index;
                         let image_list_guard = &self.image_list;
                         let mut image_list = image_list_guard.lock().unwrap();
                         image_list.remove(deleted_image_index);
                     }
-                    self.load_prev_image(&Instant::now());
+                    // Without wrapping around, deleting the first image shows the one after it
+                    if deleted_image_index == 0 && !self.wrap_around_enabled {
+                        self.show_image_at(0, &Instant::now());
+                    } else {
+                        self.load_prev_image(&Instant::now());
+                    }
                 } else {
                     self.close_current_image();
                 }
@@ -482,11 +1231,20 @@ impl AppState {
         }
     }
 
+    /// The file on disk that holds the current image, which is the archive for archive entries
+    fn get_current_file_path(&self, image_list: &[PathBuf]) -> PathBuf {
+        let path = &image_list[self.current_image_index];
+        match split_archive_path(path) {
+            Some((archive_path, _entry_name)) => archive_path,
+            None => path.to_path_buf(),
+        }
+    }
+
     pub fn open_with(&self) {
         if self.has_image() {
             let image_list_guard = &self.image_list;
             let image_list = image_list_guard.lock().unwrap();
-            let path = image_list[self.current_image_index].to_path_buf();
+            let path = self.get_current_file_path(&image_list);
             let _result = open_with::open_with(path);
         }
     }
@@ -495,7 +1253,7 @@ impl AppState {
         if self.has_image() {
             let image_list_guard = &self.image_list;
             let image_list = image_list_guard.lock().unwrap();
-            let path = image_list[self.current_image_index].to_path_buf();
+            let path = self.get_current_file_path(&image_list);
             let _result = open_with::show_in_folder(path);
         }
     }
@@ -504,55 +1262,520 @@ impl AppState {
         if self.has_image() {
             let image_list_guard = &self.image_list;
             let image_list = image_list_guard.lock().unwrap();
-            let path = image_list[self.current_image_index].to_path_buf();
+            let path = self.get_current_file_path(&image_list);
             let _result = open_with::show_properties(path);
         }
     }
 
+    pub fn recursive_browsing_enabled(&self) -> bool {
+        self.recursive_browsing_enabled
+    }
+
+    pub fn recursive_browsing_enable_toggle(&mut self) {
+        self.recursive_browsing_enabled ^= true;
+        self.session_overrides.remove("recursive_browsing_enabled");
+        self.rescan_folder();
+    }
+
+    pub fn folder_depth_limit(&self) -> Option<usize> {
+        self.folder_depth_limit
+    }
+
+    pub fn set_folder_depth_limit(&mut self, depth_limit: Option<usize>) {
+        self.folder_depth_limit = depth_limit;
+        self.rescan_folder();
+    }
+
+    pub fn skip_hidden_folders(&self) -> bool {
+        self.skip_hidden_folders
+    }
+
+    pub fn skip_hidden_folders_toggle(&mut self) {
+        self.skip_hidden_folders ^= true;
+        self.rescan_folder();
+    }
+
+    pub fn single_instance_enabled(&self) -> bool {
+        self.single_instance_enabled
+    }
+
+    pub fn single_instance_enable_toggle(&mut self) {
+        self.single_instance_enabled ^= true;
+        if self.single_instance_enabled {
+            let event_sink = self.druid_event_sink.lock().unwrap().clone();
+            single_instance::listen(event_sink);
+        } else {
+            single_instance::stop_listening();
+        }
+    }
+
+    pub fn sort_mode(&self) -> SortMode {
+        self.sort_mode
+    }
+
+    pub fn set_sort_mode(&mut self, sort_mode: SortMode) {
+        self.sort_mode = sort_mode;
+        self.session_overrides.remove("sort_mode");
+        self.rescan_folder();
+    }
+
+    pub fn zoom_mode(&self) -> ZoomMode {
+        self.zoom_mode
+    }
+
+    pub fn set_zoom_mode(&mut self, zoom_mode: ZoomMode) {
+        self.zoom_mode = zoom_mode;
+        self.session_overrides.remove("zoom_mode");
+    }
+
     pub fn blur_enabled(&self) -> bool {
         self.blur_enabled
     }
 
     pub fn blur_enable_toggle(&mut self) {
         self.blur_enabled ^= true;
+        self.session_overrides.remove("blur_enabled");
     }
 
     pub fn image_filtering_enable_toggle(&mut self) {
         self.filtering_enabled ^= true;
+        self.session_overrides.remove("image_filtering_enabled");
     }
 
     pub fn image_filtering_enabled(&self) -> bool {
         self.filtering_enabled
     }
 
-    pub fn load_prefs(&mut self) {
-        let key = "preferences";
-        let result = HashMap::<String, bool>::load(&APP_SIG, key);
+    pub fn pixel_grid_enable_toggle(&mut self) {
+        self.pixel_grid_enabled ^= true;
+    }
+
+    pub fn pixel_grid_enabled(&self) -> bool {
+        self.pixel_grid_enabled
+    }
+
+    pub fn set_tile_grid_size(&mut self, size: Option<u32>) {
+        self.tile_grid_size = size;
+    }
+
+    pub fn tile_grid_size(&self) -> Option<u32> {
+        self.tile_grid_size
+    }
+
+    pub fn set_canvas_background(&mut self, background: CanvasBackground) {
+        self.canvas_background = background;
+    }
+
+    pub fn get_canvas_background(&self) -> &CanvasBackground {
+        &self.canvas_background
+    }
+
+    pub fn theme_mode(&self) -> ThemeMode {
+        self.theme_mode
+    }
+
+    pub fn set_theme_mode(&mut self, theme_mode: ThemeMode) {
+        self.theme_mode = theme_mode;
+        self.update_dark_theme();
+    }
+
+    /// Called when the desktop switches between light and dark
+    pub fn set_system_dark_theme(&mut self, dark: bool) {
+        self.system_dark_theme = dark;
+        self.update_dark_theme();
+    }
+
+    fn update_dark_theme(&mut self) {
+        self.dark_theme_enabled = match self.theme_mode {
+            ThemeMode::System => self.system_dark_theme,
+            ThemeMode::Light => false,
+            ThemeMode::Dark => true,
+        };
+    }
+
+    pub fn get_channel_view(&self) -> ChannelView {
+        self.channel_view
+    }
+
+    pub fn set_channel_view(&mut self, view: ChannelView) {
+        self.channel_view = view;
+    }
+
+    pub fn cycle_channel_view(&mut self) {
+        self.channel_view = self.channel_view.next();
+    }
+
+    pub fn get_render_settings(&self) -> RenderSettings {
+        RenderSettings {
+            channel_view: self.channel_view,
+            exposure: self.exposure,
+            gamma: self.gamma,
+            tone_mapping: self.tone_mapping,
+            color_revision: self.color_management.get_revision(),
+        }
+    }
+
+    pub fn get_color_management(&self) -> &ColorManagement {
+        &self.color_management
+    }
+
+    pub fn show_profile_dialog(&self, proofing: bool) {
+        if let Some(window_id) = self.window_id {
+            let icc_profiles = FileSpec::new("ICC profiles", &["icc", "ICC", "icm", "ICM"]);
+            let (title, accept_command) = if proofing {
+                ("Choose a proofing profile", PROOFING_PROFILE_SELECTED)
+            } else {
+                ("Choose the monitor profile", MONITOR_PROFILE_SELECTED)
+            };
+            let options = FileDialogOptions::new()
+                .allowed_types(vec![icc_profiles])
+                .name_label("Profile")
+                .title(title)
+                .button_text("Use profile")
+                .accept_command(accept_command);
+
+            let event_sink = self.druid_event_sink.lock().unwrap();
+            event_sink
+                .submit_command(SHOW_OPEN_PANEL, options, window_id)
+                .expect("Failed to send command");
+        }
+    }
 
-        if let Ok(preferences) = result {
-            preferences
-                .get("blur_enabled")
-                .map(|b| self.blur_enabled = *b);
+    pub fn set_monitor_profile_path(&mut self, path: Option<String>) {
+        match self.color_management.set_monitor_profile_path(path) {
+            Ok(()) => self.redraw_widgets(),
+            Err(error) => println!("Failed to load monitor profile: {}", error),
+        }
+    }
 
-            preferences
-                .get("image_filtering_enabled")
-                .map(|f| self.filtering_enabled = *f);
+    pub fn set_proofing_profile_path(&mut self, path: Option<String>) {
+        match self.color_management.set_proofing_profile_path(path) {
+            Ok(()) => self.redraw_widgets(),
+            Err(error) => println!("Failed to load proofing profile: {}", error),
+        }
+    }
+
+    pub fn soft_proofing_enable_toggle(&mut self) {
+        let enabled = !self.color_management.soft_proofing_enabled();
+        self.color_management.set_soft_proofing_enabled(enabled);
+        self.redraw_widgets();
+    }
+
+    pub fn get_exposure(&self) -> f64 {
+        self.exposure
+    }
+
+    pub fn set_exposure(&mut self, exposure: f64) {
+        self.exposure = exposure;
+    }
+
+    pub fn get_gamma(&self) -> f64 {
+        self.gamma
+    }
+
+    pub fn set_gamma(&mut self, gamma: f64) {
+        self.gamma = gamma;
+    }
+
+    pub fn get_tone_mapping(&self) -> ToneMapping {
+        self.tone_mapping
+    }
+
+    pub fn set_tone_mapping(&mut self, tone_mapping: ToneMapping) {
+        self.tone_mapping = tone_mapping;
+    }
+
+    pub fn has_hdr_image(&self) -> bool {
+        let image_guard = self.current_image.lock().unwrap();
+        match &*image_guard {
+            ImageState::Loaded(image) => image.is_hdr(),
+            _ => false,
+        }
+    }
+
+    pub fn get_dynamic_range_description(&self) -> String {
+        let image_guard = self.current_image.lock().unwrap();
+        if let ImageState::Loaded(image) = &*image_guard {
+            if let Some(dynamic_range) = image.get_dynamic_range() {
+                return format!(
+                    "Dynamic range: {:.1} stops ({:.4} to {:.1})",
+                    dynamic_range.get_stops(),
+                    dynamic_range.min_luminance,
+                    dynamic_range.max_luminance
+                );
+            }
+        }
+        "Dynamic range: unknown".to_string()
+    }
+
+    pub fn histogram_enabled(&self) -> bool {
+        self.histogram_enabled
+    }
+
+    pub fn histogram_enable_toggle(&mut self) {
+        self.histogram_enabled ^= true;
+        if self.histogram_enabled {
+            self.compute_histogram();
+        }
+    }
+
+    fn compute_histogram(&self) {
+        let current_image: DynamicImage;
+        let timestamp: Instant;
+        {
+            let image_state_guard = self.get_image_ref();
+            let image_state = image_state_guard.lock().unwrap();
+            if let ImageState::Loaded(image) = &*image_state {
+                if image.has_histogram() {
+                    return;
+                }
+                current_image = image.get_image().clone();
+                timestamp = *image.get_timestamp();
+            } else {
+                return;
+            }
+        }
+
+        let event_sink_mutex_ref = self.druid_event_sink.clone();
+        thread::spawn(move || {
+            let histogram = Histogram::from_image(&current_image);
+            let event_sink_mutex = event_sink_mutex_ref.lock().unwrap();
+            let event_sink = &*event_sink_mutex;
+            event_sink
+                .submit_command(
+                    HISTOGRAM_COMPLETE,
+                    (timestamp, Arc::new(histogram)),
+                    Target::Auto,
+                )
+                .expect("Failed to send histogram complete command");
+        });
+    }
+
+    pub fn set_histogram(&mut self, image_timestamp: &Instant, histogram: Arc<Histogram>) {
+        let image_state_guard = self.get_image_ref();
+        let mut image_state = image_state_guard.lock().unwrap();
+        // The histogram is discarded if the image changed while it was being computed
+        if let ImageState::Loaded(image) = &mut *image_state {
+            if image.get_timestamp() == image_timestamp {
+                image.set_histogram(histogram);
+            }
+        }
+    }
+
+    pub fn load_prefs(&mut self, settings: Settings) {
+        self.settings_save_disabled = settings.save_disabled;
+        self.blur_enabled = settings.blur_enabled;
+        self.filtering_enabled = settings.image_filtering_enabled;
+        self.pixel_grid_enabled = settings.pixel_grid_enabled;
+        self.histogram_enabled = settings.histogram_enabled;
+        self.canvas_background = settings.canvas_background;
+        self.set_theme_mode(settings.theme);
+        self.sort_mode = settings.sort_mode;
+        self.zoom_mode = settings.zoom_mode;
+        self.recursive_browsing_enabled = settings.recursive_browsing_enabled;
+        self.folder_depth_limit = settings.folder_depth_limit;
+        self.skip_hidden_folders = settings.skip_hidden_folders;
+        self.single_instance_enabled = settings.single_instance_enabled;
+        self.slideshow_delay = settings.slideshow_interval;
+        self.prefetch_count = settings.prefetch_count;
+        self.set_cache_memory_limit_mb(settings.cache_memory_limit_mb);
+        self.wrap_around_enabled = settings.wrap_around_enabled;
+        self.confirm_delete_enabled = settings.confirm_delete;
+        let (key_bindings, errors) = KeyBindings::from_settings(&settings.key_bindings);
+        for error in errors {
+            println!("Settings: key_bindings.{}", error);
+        }
+        self.key_bindings = key_bindings;
+        self.restore_session_enabled = settings.restore_session;
+        self.recent_paths = Arc::new(settings.recent_paths.iter().map(PathBuf::from).collect());
+        self.prune_recent_paths();
+        self.last_image = settings.last_image.map(PathBuf::from);
+        self.window_size = settings
+            .window_size
+            .map(|[width, height]| Size::new(width, height));
+        self.window_position = settings.window_position.map(|[x, y]| Point::new(x, y));
+        self.window_maximized = settings.window_maximized;
+        if let Some(path) = settings.monitor_profile_path {
+            if let Err(error) = self.color_management.set_monitor_profile_path(Some(path)) {
+                println!("Settings: monitor_profile_path: {}", error);
+            }
+        }
+        if let Some(path) = settings.proofing_profile_path {
+            if let Err(error) = self.color_management.set_proofing_profile_path(Some(path)) {
+                println!("Settings: proofing_profile_path: {}", error);
+            }
         }
     }
 
     pub fn save_prefs(&self) {
-        let mut preferences: HashMap<String, bool> = PreferencesMap::new();
+        if self.settings_save_disabled {
+            return;
+        }
+        let mut settings = Settings {
+            blur_enabled: self.blur_enabled,
+            image_filtering_enabled: self.filtering_enabled,
+            pixel_grid_enabled: self.pixel_grid_enabled,
+            histogram_enabled: self.histogram_enabled,
+            canvas_background: self.canvas_background.clone(),
+            theme: self.theme_mode,
+            sort_mode: self.sort_mode,
+            zoom_mode: self.zoom_mode,
+            recursive_browsing_enabled: self.recursive_browsing_enabled,
+            folder_depth_limit: self.folder_depth_limit,
+            skip_hidden_folders: self.skip_hidden_folders,
+            single_instance_enabled: self.single_instance_enabled,
+            slideshow_interval: self.slideshow_delay,
+            prefetch_count: self.prefetch_count,
+            cache_memory_limit_mb: self.cache_memory_limit_mb,
+            wrap_around_enabled: self.wrap_around_enabled,
+            confirm_delete: self.confirm_delete_enabled,
+            restore_session: self.restore_session_enabled,
+            last_image: self
+                .get_last_image()
+                .map(|path| path.to_string_lossy().to_string()),
+            window_size: self.window_size.map(|size| [size.width, size.height]),
+            window_position: self
+                .window_position
+                .map(|position| [position.x, position.y]),
+            window_maximized: self.window_maximized,
+            recent_paths: self
+                .recent_paths
+                .iter()
+                .map(|path| path.to_string_lossy().to_string())
+                .collect(),
+            monitor_profile_path: self.color_management.get_monitor_profile_path().cloned(),
+            proofing_profile_path: self.color_management.get_proofing_profile_path().cloned(),
+            key_bindings: self.key_bindings.to_settings(),
+            ..Settings::default()
+        };
+
+        // Options given on the command line keep their stored values
+        if !self.session_overrides.is_empty() {
+            let (stored_settings, _errors) = Settings::load();
+            for key in &self.session_overrides {
+                settings.copy_setting(&stored_settings, key);
+            }
+        }
 
-        preferences.insert("blur_enabled".into(), self.blur_enabled);
-        preferences.insert("image_filtering_enabled".into(), self.filtering_enabled);
+        if let Err(error) = settings.save() {
+            println!("Failed to save settings: {}", error);
+        }
+    }
 
-        let key = "preferences";
-        let _result = preferences.save(&APP_SIG, key);
+    /// Applies command line options on top of the loaded preferences, for this session only
+    pub fn apply_session_overrides(&mut self, cli: &Cli) {
+        if cli.recursive {
+            self.recursive_browsing_enabled = true;
+            self.session_overrides.insert("recursive_browsing_enabled");
+        }
+        if cli.no_blur {
+            self.blur_enabled = false;
+            self.session_overrides.insert("blur_enabled");
+        }
+        if let Some(filter) = cli.filter {
+            self.filtering_enabled = matches!(filter, Filter::Bilinear);
+            self.session_overrides.insert("image_filtering_enabled");
+        }
+        if let Some(sort_mode) = cli.sort {
+            self.sort_mode = sort_mode;
+            self.session_overrides.insert("sort_mode");
+        }
+        if let Some(zoom_mode) = cli.zoom {
+            self.zoom_mode = zoom_mode;
+            self.session_overrides.insert("zoom_mode");
+        }
+        // Launches forwarded from other processes can only turn these on
+        if cli.fullscreen {
+            self.fullscreen_enabled = true;
+        }
+        if cli.slideshow.is_some() {
+            self.slideshow_interval = cli.slideshow;
+        }
     }
 
     pub fn exit(&mut self) {
-        self.close_current_image();
+        // Saved first, while the current image is still known
         self.save_prefs();
+        self.close_current_image();
         Application::global().quit()
     }
 }
+
+/// Keeps each folder's images together, with a folder's own images before its subfolders
+fn sort_files(files: &mut [PathBuf], sort_mode: SortMode) {
+    let file_name = |path: &Path| path.file_name().unwrap_or_default().to_os_string();
+    match sort_mode {
+        SortMode::Name => files.sort_by(|a, b| {
+            a.parent()
+                .cmp(&b.parent())
+                .then_with(|| a.file_name().cmp(&b.file_name()))
+        }),
+        SortMode::Natural => files.sort_by(|a, b| {
+            a.parent().cmp(&b.parent()).then_with(|| {
+                natural_cmp(
+                    &file_name(a).to_string_lossy(),
+                    &file_name(b).to_string_lossy(),
+                )
+            })
+        }),
+        // Metadata is read once per file rather than on every comparison
+        SortMode::Modified => files.sort_by_cached_key(|path| {
+            let modified = path.metadata().and_then(|m| m.modified()).ok();
+            (
+                path.parent().map(Path::to_path_buf),
+                modified,
+                file_name(path),
+            )
+        }),
+        SortMode::Size => files.sort_by_cached_key(|path| {
+            let size = path.metadata().map(|m| m.len()).ok();
+            (path.parent().map(Path::to_path_buf), size, file_name(path))
+        }),
+    }
+}
+
+/// Gathers supported images from a folder, descending into subfolders while the depth limit
+/// allows. Symbolic links to folders aren't followed, so link cycles can't recurse forever.
+fn collect_folder_images(
+    folder: &Path,
+    depth_limit: Option<usize>,
+    skip_hidden_folders: bool,
+    files: &mut Vec<PathBuf>,
+) {
+    let entries = match folder.read_dir() {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let entry_path = entry.path();
+        let is_folder = entry
+            .file_type()
+            .map_or(false, |file_type| file_type.is_dir());
+        if is_folder {
+            if depth_limit == Some(0) || (skip_hidden_folders && is_hidden(&entry)) {
+                continue;
+            }
+            let remaining_depth = depth_limit.map(|depth_limit| depth_limit - 1);
+            collect_folder_images(&entry_path, remaining_depth, skip_hidden_folders, files);
+        } else if let Some(file_extension) = entry_path.extension() {
+            if is_supported_extension(file_extension) {
+                files.push(entry_path);
+            }
+        }
+    }
+}
+
+fn is_hidden(entry: &std::fs::DirEntry) -> bool {
+    if entry.file_name().to_string_lossy().starts_with('.') {
+        return true;
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::MetadataExt;
+        const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+        if let Ok(metadata) = entry.metadata() {
+            return metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0;
+        }
+    }
+    false
+}
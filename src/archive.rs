@@ -0,0 +1,171 @@
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+use crate::image_loader::is_supported_extension;
+
+/// Lowercase extensions of the archives that can be browsed like folders
+const ARCHIVE_EXTENSIONS: [&str; 4] = ["zip", "cbz", "tar", "cbt"];
+/// Entries are read into memory whole, so anything claiming to be bigger is refused
+const MAX_ENTRY_SIZE: u64 = 256 * 1024 * 1024;
+
+#[derive(Clone, Copy, PartialEq)]
+enum ArchiveKind {
+    Zip,
+    Tar,
+}
+
+fn get_archive_kind(path: &Path) -> Option<ArchiveKind> {
+    let extension = path.extension()?.to_ascii_lowercase();
+    match ARCHIVE_EXTENSIONS
+        .iter()
+        .position(|archive_extension| extension == *archive_extension)?
+    {
+        0 | 1 => Some(ArchiveKind::Zip),
+        _ => Some(ArchiveKind::Tar),
+    }
+}
+
+pub fn is_archive_path(path: &Path) -> bool {
+    path.is_file() && get_archive_kind(path).is_some()
+}
+
+/// Images inside an archive are addressed as if the archive were a folder, as in
+/// "comic.cbz/chapter 1/page 2.png". Returns the archive and the entry name for such paths.
+pub fn split_archive_path(path: &Path) -> Option<(PathBuf, String)> {
+    let archive = path
+        .ancestors()
+        .skip(1)
+        .find(|ancestor| is_archive_path(ancestor))?;
+    let entry = path
+        .strip_prefix(archive)
+        .ok()?
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    Some((archive.to_path_buf(), entry))
+}
+
+/// Lists the images in an archive as virtual paths, in natural order
+pub fn list_archive_images(archive: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut entry_names = match get_archive_kind(archive).ok_or("Not an archive")? {
+        ArchiveKind::Zip => {
+            let zip_archive = zip::ZipArchive::new(File::open(archive)?)?;
+            zip_archive
+                .file_names()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        }
+        ArchiveKind::Tar => {
+            let mut tar_archive = tar::Archive::new(File::open(archive)?);
+            let mut names = Vec::new();
+            for entry in tar_archive.entries()? {
+                let entry = entry?;
+                if entry.header().entry_type().is_file() {
+                    names.push(entry.path()?.to_string_lossy().to_string());
+                }
+            }
+            names
+        }
+    };
+
+    // Names are joined to the archive path, so one like "/x.png" or "../x.png" would point
+    // at a real file outside the archive
+    entry_names.retain(|name| {
+        let entry_path = Path::new(name);
+        entry_path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+            && entry_path.extension().map_or(false, is_supported_extension)
+    });
+    entry_names.sort_by(|a, b| natural_cmp(a, b));
+    Ok(entry_names
+        .into_iter()
+        .map(|name| archive.join(name))
+        .collect())
+}
+
+/// Reads a single entry into memory, so it can be decoded without extracting the archive
+pub fn read_archive_entry(archive: &Path, entry_name: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    match get_archive_kind(archive).ok_or("Not an archive")? {
+        ArchiveKind::Zip => {
+            let mut zip_archive = zip::ZipArchive::new(File::open(archive)?)?;
+            let entry = zip_archive.by_name(entry_name)?;
+            let size = entry.size();
+            read_entry_data(entry, size)
+        }
+        ArchiveKind::Tar => {
+            // Tar has no index, so the archive is scanned up to the entry
+            let mut tar_archive = tar::Archive::new(File::open(archive)?);
+            let entry = tar_archive
+                .entries()?
+                .flatten()
+                .find(|entry| {
+                    entry
+                        .path()
+                        .map_or(false, |path| path.to_string_lossy() == entry_name)
+                })
+                .ok_or("Entry not found in archive")?;
+            let size = entry.size();
+            read_entry_data(entry, size)
+        }
+    }
+}
+
+/// Reads no more than the size an entry declares, so a compressed entry that inflates past it
+/// can't exhaust memory
+fn read_entry_data(entry: impl Read, size: u64) -> Result<Vec<u8>, Box<dyn Error>> {
+    if size > MAX_ENTRY_SIZE {
+        return Err("Archive entry is too large".into());
+    }
+    let mut data = Vec::with_capacity(size as usize);
+    entry.take(size).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Compares names so that embedded numbers sort by value, putting "page 2" before "page 10"
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a_char), Some(b_char)) if a_char.is_ascii_digit() && b_char.is_ascii_digit() => {
+                let mut a_number = String::new();
+                while let Some(digit) = a_chars.next_if(|c| c.is_ascii_digit()) {
+                    a_number.push(digit);
+                }
+                let mut b_number = String::new();
+                while let Some(digit) = b_chars.next_if(|c| c.is_ascii_digit()) {
+                    b_number.push(digit);
+                }
+                // Compare by magnitude without parsing, so long digit runs can't overflow
+                let a_trimmed = a_number.trim_start_matches('0');
+                let b_trimmed = b_number.trim_start_matches('0');
+                let ordering = a_trimmed
+                    .len()
+                    .cmp(&b_trimmed.len())
+                    .then_with(|| a_trimmed.cmp(b_trimmed));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(a_char), Some(b_char)) => {
+                let ordering = a_char
+                    .to_lowercase()
+                    .cmp(b_char.to_lowercase())
+                    .then_with(|| a_char.cmp(&b_char));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
+    }
+}
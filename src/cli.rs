@@ -0,0 +1,160 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::types::{SortMode, ZoomMode};
+
+/// Options that override the saved preferences for this session only
+#[derive(Parser)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    /// Work on an image without opening a window
+    #[command(subcommand)]
+    pub command: Option<HeadlessCommand>,
+
+    /// Images, folders, archives or list files (.m3u, .txt) to open. Use "-" to read paths from
+    /// stdin.
+    pub paths: Vec<String>,
+
+    /// Start in fullscreen
+    #[arg(long)]
+    pub fullscreen: bool,
+
+    /// Advance to the next image every SECS seconds
+    #[arg(long, value_name = "SECS", value_parser = parse_slideshow_interval)]
+    pub slideshow: Option<f64>,
+
+    /// How to scale opened images: fit, 1:1, or a percentage such as 150%
+    #[arg(long, value_parser = parse_zoom_mode)]
+    pub zoom: Option<ZoomMode>,
+
+    /// Order of images in a folder: name, natural, modified or size
+    #[arg(long, value_name = "MODE", value_parser = parse_sort_mode)]
+    pub sort: Option<SortMode>,
+
+    /// Include images in subfolders
+    #[arg(long)]
+    pub recursive: bool,
+
+    /// Zero-based position in the image list to start at
+    #[arg(long, value_name = "INDEX")]
+    pub start_index: Option<usize>,
+
+    /// Turn off blur effects
+    #[arg(long)]
+    pub no_blur: bool,
+
+    /// Filtering used when scaling images
+    #[arg(long, value_enum)]
+    pub filter: Option<Filter>,
+
+    /// Accept remote control commands on a Unix socket at PATH
+    #[arg(long, value_name = "PATH")]
+    pub control_socket: Option<PathBuf>,
+}
+
+impl Cli {
+    /// The options given, without paths, as they'd be passed on the command line. The control
+    /// socket belongs to the process that opened it, so it's left out.
+    pub fn to_option_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if self.fullscreen {
+            args.push("--fullscreen".to_string());
+        }
+        if let Some(interval) = self.slideshow {
+            args.extend(["--slideshow".to_string(), interval.to_string()]);
+        }
+        if let Some(zoom_mode) = self.zoom {
+            args.extend(["--zoom".to_string(), zoom_mode.to_pref_string()]);
+        }
+        if let Some(sort_mode) = self.sort {
+            args.extend(["--sort".to_string(), sort_mode.to_pref_string()]);
+        }
+        if self.recursive {
+            args.push("--recursive".to_string());
+        }
+        if let Some(start_index) = self.start_index {
+            args.extend(["--start-index".to_string(), start_index.to_string()]);
+        }
+        if self.no_blur {
+            args.push("--no-blur".to_string());
+        }
+        if let Some(filter) = self.filter.and_then(|filter| filter.to_possible_value()) {
+            args.extend(["--filter".to_string(), filter.get_name().to_string()]);
+        }
+        args
+    }
+}
+
+#[derive(Subcommand)]
+pub enum HeadlessCommand {
+    /// Print the dimensions, format and EXIF metadata of an image
+    Info {
+        file: PathBuf,
+        /// Print JSON instead of text
+        #[arg(long)]
+        json: bool,
+    },
+    /// Convert an image to the format given by the output file's extension
+    Convert {
+        input: PathBuf,
+        output: PathBuf,
+        /// Turn the image upright according to its EXIF orientation
+        #[arg(long)]
+        auto_orient: bool,
+        /// Rotate clockwise by 90, 180 or 270 degrees
+        #[arg(long, value_name = "DEGREES", value_parser = parse_rotation)]
+        rotate: Option<u32>,
+        /// Scale down or up to fit within the given size, keeping the aspect ratio
+        #[arg(long, value_name = "WIDTHxHEIGHT", value_parser = parse_size)]
+        resize: Option<(u32, u32)>,
+    },
+    /// Write an upright thumbnail that fits within a square
+    Thumb {
+        input: PathBuf,
+        output: PathBuf,
+        /// Side length of the square, in pixels
+        #[arg(long, default_value_t = 256)]
+        size: u32,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+}
+
+fn parse_slideshow_interval(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(seconds) if seconds > 0. && seconds.is_finite() => Ok(seconds),
+        _ => Err("expected a positive number of seconds".to_string()),
+    }
+}
+
+fn parse_zoom_mode(value: &str) -> Result<ZoomMode, String> {
+    ZoomMode::from_pref_string(value)
+        .ok_or_else(|| "expected fit, 1:1 or a percentage such as 150%".to_string())
+}
+
+fn parse_sort_mode(value: &str) -> Result<SortMode, String> {
+    SortMode::from_pref_string(value)
+        .ok_or_else(|| "expected name, natural, modified or size".to_string())
+}
+
+fn parse_rotation(value: &str) -> Result<u32, String> {
+    match value.parse::<u32>() {
+        Ok(degrees) if degrees % 90 == 0 && degrees < 360 => Ok(degrees),
+        _ => Err("expected 90, 180 or 270".to_string()),
+    }
+}
+
+fn parse_size(value: &str) -> Result<(u32, u32), String> {
+    let (width, height) = value
+        .split_once(['x', 'X'])
+        .ok_or("expected a size such as 1920x1080")?;
+    match (width.parse::<u32>(), height.parse::<u32>()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err("expected a size such as 1920x1080".to_string()),
+    }
+}
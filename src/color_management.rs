@@ -0,0 +1,148 @@
+use std::error::Error;
+use std::sync::Arc;
+
+use druid::Data;
+use image::RgbaImage;
+use lcms2::{Flags, Intent, PixelFormat, Profile, Transform};
+
+/// The monitor and proofing profiles used to convert decoded images for display. Without a
+/// monitor profile the display is assumed to be sRGB.
+#[derive(Clone, Data)]
+pub struct ColorManagement {
+    monitor_profile_path: Option<String>,
+    proofing_profile_path: Option<String>,
+    soft_proofing_enabled: bool,
+    #[data(ignore)]
+    monitor_profile: Option<Arc<Vec<u8>>>,
+    #[data(ignore)]
+    proofing_profile: Option<Arc<Vec<u8>>>,
+    /// Bumped on every change so that cached renditions can be invalidated
+    revision: u32,
+}
+
+impl ColorManagement {
+    pub fn new() -> Self {
+        Self {
+            monitor_profile_path: None,
+            proofing_profile_path: None,
+            soft_proofing_enabled: false,
+            monitor_profile: None,
+            proofing_profile: None,
+            revision: 0,
+        }
+    }
+
+    pub fn get_revision(&self) -> u32 {
+        self.revision
+    }
+
+    pub fn get_monitor_profile_path(&self) -> Option<&String> {
+        self.monitor_profile_path.as_ref()
+    }
+
+    pub fn get_proofing_profile_path(&self) -> Option<&String> {
+        self.proofing_profile_path.as_ref()
+    }
+
+    pub fn soft_proofing_enabled(&self) -> bool {
+        self.soft_proofing_enabled
+    }
+
+    /// Loads and validates a monitor profile, or goes back to sRGB when given no path
+    pub fn set_monitor_profile_path(&mut self, path: Option<String>) -> Result<(), Box<dyn Error>> {
+        self.monitor_profile = match &path {
+            Some(path) => Some(Arc::new(read_profile(path)?)),
+            None => None,
+        };
+        self.monitor_profile_path = path;
+        self.revision += 1;
+        Ok(())
+    }
+
+    pub fn set_proofing_profile_path(
+        &mut self,
+        path: Option<String>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.proofing_profile = match &path {
+            Some(path) => Some(Arc::new(read_profile(path)?)),
+            None => None,
+        };
+        self.proofing_profile_path = path;
+        self.revision += 1;
+        Ok(())
+    }
+
+    pub fn set_soft_proofing_enabled(&mut self, enabled: bool) {
+        self.soft_proofing_enabled = enabled;
+        self.revision += 1;
+    }
+
+    /// Converts an image from its embedded profile, or sRGB if it has none, to the monitor
+    /// profile. The image is left untouched if any of the profiles can't be used.
+    pub fn convert_for_display(&self, rgba: &mut RgbaImage, source_profile: Option<&[u8]>) {
+        let proofing_profile = self
+            .proofing_profile
+            .as_ref()
+            .filter(|_| self.soft_proofing_enabled);
+        // sRGB to sRGB is a no-op, so skip the transform entirely in the common case
+        if source_profile.is_none() && self.monitor_profile.is_none() && proofing_profile.is_none()
+        {
+            return;
+        }
+
+        let result = self.build_transform(source_profile, proofing_profile.map(|p| p.as_slice()));
+        match result {
+            Ok(transform) => {
+                let mut pixels: Vec<[u8; 4]> = rgba.pixels().map(|pixel| pixel.0).collect();
+                transform.transform_in_place(&mut pixels);
+                for (pixel, converted) in rgba.pixels_mut().zip(pixels) {
+                    pixel.0 = converted;
+                }
+            }
+            Err(error) => println!("Failed to build color transform: {}", error),
+        }
+    }
+
+    fn build_transform(
+        &self,
+        source_profile: Option<&[u8]>,
+        proofing_profile: Option<&[u8]>,
+    ) -> Result<Transform<[u8; 4], [u8; 4]>, Box<dyn Error>> {
+        let source = match source_profile {
+            Some(data) => Profile::new_icc(data)?,
+            None => Profile::new_srgb(),
+        };
+        let monitor = match &self.monitor_profile {
+            Some(data) => Profile::new_icc(data)?,
+            None => Profile::new_srgb(),
+        };
+
+        let transform = match proofing_profile {
+            Some(data) => Transform::new_proofing(
+                &source,
+                PixelFormat::RGBA_8,
+                &monitor,
+                PixelFormat::RGBA_8,
+                &Profile::new_icc(data)?,
+                Intent::Perceptual,
+                Intent::RelativeColorimetric,
+                Flags::SOFT_PROOFING,
+            )?,
+            None => Transform::new(
+                &source,
+                PixelFormat::RGBA_8,
+                &monitor,
+                PixelFormat::RGBA_8,
+                Intent::Perceptual,
+            )?,
+        };
+        Ok(transform)
+    }
+}
+
+fn read_profile(path: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let data = std::fs::read(path)?;
+    // Parse once up front so a bad file is rejected when it's chosen, not on every redraw
+    Profile::new_icc(&data)?;
+    Ok(data)
+}
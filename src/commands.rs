@@ -1,12 +1,19 @@
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Instant;
 
+use crate::histogram::Histogram;
+use crate::preferences_window::build_preferences_window;
+use crate::single_instance::ForwardedLaunch;
 use crate::types::{Direction, DisplayState, NewImageContainer};
-use crate::{platform_api_calls, AppState};
-use druid::commands::OPEN_FILE;
+use crate::{
+    accept_dropped_files, build_delete_confirmation, build_settings_errors, platform_api_calls,
+    AppState,
+};
+use druid::commands::{CONFIGURE_WINDOW, OPEN_FILE};
 use druid::{
-    AppDelegate, Command, DelegateCtx, Env, Handled, Selector, SingleUse, Target, WindowHandle,
-    WindowId,
+    AppDelegate, Command, DelegateCtx, Env, FileInfo, Handled, Selector, SingleUse, Target,
+    WindowDesc, WindowHandle, WindowId,
 };
 
 pub const REDRAW_IMAGE: Selector<()> = Selector::new("redraw_image");
@@ -17,7 +24,11 @@ pub const IMAGE_LOAD_FAILURE: Selector<PathBuf> = Selector::new("image_load_fail
 pub const IMAGE_LOAD_SUCCESS: Selector<SingleUse<NewImageContainer>> =
     Selector::new("image_loaded");
 
+pub const DEVELOP_RAW: Selector<Instant> = Selector::new("develop_raw");
+pub const RAW_DEVELOP_FAILURE: Selector<()> = Selector::new("raw_develop_failure");
+
 pub const FULLSCREEN_VIEW: Selector<Instant> = Selector::new("fullscreen_view");
+pub const TOGGLE_SLIDESHOW: Selector<Instant> = Selector::new("toggle_slideshow");
 
 pub const ROTATE_LEFT: Selector<Instant> = Selector::new("rotate_left");
 pub const ROTATE_RIGHT: Selector<Instant> = Selector::new("rotate_right");
@@ -27,6 +38,7 @@ pub const IMAGE_ROTATION_COMPLETE: Selector<SingleUse<NewImageContainer>> =
 pub const ZOOM_IMAGE: Selector<Instant> = Selector::new("zoom_image");
 pub const RECENTER_IMAGE: Selector<Instant> = Selector::new("recenter_image");
 pub const REALSIZE_IMAGE: Selector<Instant> = Selector::new("realsize_image");
+pub const SCALE_IMAGE: Selector<f64> = Selector::new("scale_image");
 
 pub const DELETE_IMAGE: Selector<Instant> = Selector::new("delete_image");
 pub const LOAD_NEW_IMAGE: Selector<Instant> = Selector::new("load_new_image");
@@ -34,18 +46,53 @@ pub const LOAD_NEW_IMAGE: Selector<Instant> = Selector::new("load_new_image");
 pub const NEXT_IMAGE: Selector<Instant> = Selector::new("next_image");
 pub const PREV_IMAGE: Selector<Instant> = Selector::new("prev_image");
 
-pub struct Delegate;
+pub const NEXT_PAGE: Selector<Instant> = Selector::new("next_page");
+pub const PREV_PAGE: Selector<Instant> = Selector::new("prev_page");
+pub const NEXT_MIP_LEVEL: Selector<Instant> = Selector::new("next_mip_level");
+pub const PREV_MIP_LEVEL: Selector<Instant> = Selector::new("prev_mip_level");
+pub const TOGGLE_CUBE_CROSS: Selector<Instant> = Selector::new("toggle_cube_cross");
+
+pub const CYCLE_CHANNEL_VIEW: Selector<Instant> = Selector::new("cycle_channel_view");
+
+pub const TOGGLE_HISTOGRAM: Selector<Instant> = Selector::new("toggle_histogram");
+pub const HISTOGRAM_COMPLETE: Selector<(Instant, Arc<Histogram>)> =
+    Selector::new("histogram_complete");
+
+pub const FILES_DROPPED: Selector<Vec<PathBuf>> = Selector::new("files_dropped");
+
+pub const FORWARDED_LAUNCH: Selector<SingleUse<ForwardedLaunch>> =
+    Selector::new("forwarded_launch");
+
+pub const SHOW_PREFERENCES: Selector<()> = Selector::new("show_preferences");
+pub const SYSTEM_THEME_CHANGED: Selector<bool> = Selector::new("system_theme_changed");
+
+pub const MONITOR_PROFILE_SELECTED: Selector<FileInfo> = Selector::new("monitor_profile_selected");
+pub const PROOFING_PROFILE_SELECTED: Selector<FileInfo> =
+    Selector::new("proofing_profile_selected");
+
+pub struct Delegate {
+    window_handle: Option<WindowHandle>,
+    preferences_window_id: Option<WindowId>,
+    preferences_window_handle: Option<WindowHandle>,
+    /// Problems loading the settings, shown once the viewer window is open
+    settings_errors: Vec<String>,
+}
 
 impl Delegate {
-    pub fn new() -> Self {
-        Self
+    pub fn new(settings_errors: Vec<String>) -> Self {
+        Self {
+            window_handle: None,
+            preferences_window_id: None,
+            preferences_window_handle: None,
+            settings_errors,
+        }
     }
 }
 
 impl AppDelegate<AppState> for Delegate {
     fn command(
         &mut self,
-        _ctx: &mut DelegateCtx,
+        ctx: &mut DelegateCtx,
         _target: Target,
         cmd: &Command,
         data: &mut AppState,
@@ -59,14 +106,35 @@ impl AppDelegate<AppState> for Delegate {
             data.set_loading_state(false);
             data.image_load_failure(image_path);
             Handled::Yes
+        } else if let Some(command_timestamp) = cmd.get(DEVELOP_RAW) {
+            data.develop_raw_image(command_timestamp);
+            Handled::Yes
+        } else if cmd.get(RAW_DEVELOP_FAILURE).is_some() {
+            data.set_loading_state(false);
+            Handled::Yes
         } else if let Some(command_timestamp) = cmd.get(NEXT_IMAGE) {
             data.load_next_image(command_timestamp);
             Handled::Yes
         } else if let Some(command_timestamp) = cmd.get(PREV_IMAGE) {
             data.load_prev_image(command_timestamp);
             Handled::Yes
+        } else if cmd.get(NEXT_PAGE).is_some() {
+            data.step_page(1);
+            Handled::Yes
+        } else if cmd.get(PREV_PAGE).is_some() {
+            data.step_page(-1);
+            Handled::Yes
+        } else if cmd.get(NEXT_MIP_LEVEL).is_some() {
+            data.step_mip_level(1);
+            Handled::Yes
+        } else if cmd.get(PREV_MIP_LEVEL).is_some() {
+            data.step_mip_level(-1);
+            Handled::Yes
+        } else if cmd.get(TOGGLE_CUBE_CROSS).is_some() {
+            data.toggle_cube_cross_layout();
+            Handled::Yes
         }
-        // The next three events are also partially handled by the ContainerWidget
+        // The next four events are also partially handled by the ContainerWidget
         else if cmd.get(ZOOM_IMAGE).is_some() {
             data.set_display_state(DisplayState::Zoomed(true));
             Handled::No
@@ -76,11 +144,48 @@ impl AppDelegate<AppState> for Delegate {
         } else if cmd.get(REALSIZE_IMAGE).is_some() {
             data.set_display_state(DisplayState::RealSize(true));
             Handled::No
+        } else if cmd.get(SCALE_IMAGE).is_some() {
+            data.set_display_state(DisplayState::Zoomed(false));
+            Handled::No
         } else if cmd.get(FULLSCREEN_VIEW).is_some() {
-            data.show_fullscreen_slideshow();
+            data.fullscreen_toggle();
+            if let Some(window_id) = data.get_window_id() {
+                ctx.submit_command(
+                    CONFIGURE_WINDOW
+                        .with(data.get_window_config())
+                        .to(window_id),
+                );
+            }
+            Handled::Yes
+        } else if cmd.get(TOGGLE_SLIDESHOW).is_some() {
+            data.slideshow_toggle();
             Handled::Yes
         } else if cmd.get(DELETE_IMAGE).is_some() {
-            data.delete_image();
+            if data.confirm_delete_enabled() && data.has_image() {
+                let confirmation_window = WindowDesc::new(build_delete_confirmation())
+                    .title("Move to trash")
+                    .window_size((360., 140.))
+                    .resizable(false);
+                ctx.new_window(confirmation_window);
+            } else {
+                data.delete_image();
+            }
+            Handled::Yes
+        } else if let Some(dark) = cmd.get(SYSTEM_THEME_CHANGED) {
+            data.set_system_dark_theme(*dark);
+            Handled::Yes
+        } else if cmd.get(SHOW_PREFERENCES).is_some() {
+            // Only one preferences window is opened, and asking again brings it forward
+            if let Some(window_handle) = &self.preferences_window_handle {
+                window_handle.bring_to_front_and_focus();
+            } else if self.preferences_window_id.is_none() {
+                let preferences_window = WindowDesc::new(build_preferences_window())
+                    .title("Foxfire - Preferences")
+                    .with_min_size((360., 240.))
+                    .window_size((480., 640.));
+                self.preferences_window_id = Some(preferences_window.id);
+                ctx.new_window(preferences_window);
+            }
             Handled::Yes
         } else if let Some(command_timestamp) = cmd.get(ROTATE_LEFT) {
             data.rotate_in_memory(Direction::Left, command_timestamp);
@@ -90,6 +195,7 @@ impl AppDelegate<AppState> for Delegate {
             Handled::Yes
         } else if let Some(image_wrapper) = cmd.get(IMAGE_ROTATION_COMPLETE) {
             data.set_current_image(image_wrapper.take());
+            data.set_image_rotated();
             data.set_rotating_state(false);
             data.redraw_widgets();
             Handled::Yes
@@ -101,15 +207,61 @@ impl AppDelegate<AppState> for Delegate {
         } else if let Some(file_info) = cmd.get(OPEN_FILE) {
             let file_path = file_info.path.to_str();
             if let Some(path_string) = file_path {
-                data.startup(path_string.to_string());
+                data.startup(path_string.to_string(), None);
             } else {
                 println!("Failed to parse image path")
             }
 
+            Handled::Yes
+        } else if let Some(paths) = cmd.get(FILES_DROPPED) {
+            // A single file or folder opens like the file dialog, several become the image list
+            match paths.as_slice() {
+                [] => {}
+                [path] => data.startup(path.to_string_lossy().to_string(), None),
+                paths => data.startup_with_list(paths.to_vec(), None),
+            }
+            Handled::Yes
+        } else if let Some(forwarded_launch) = cmd.get(FORWARDED_LAUNCH) {
+            if let Some(forwarded_launch) = forwarded_launch.take() {
+                let was_fullscreen = data.fullscreen_enabled();
+                data.open_forwarded_launch(forwarded_launch);
+                if data.fullscreen_enabled() != was_fullscreen {
+                    if let Some(window_id) = data.get_window_id() {
+                        ctx.submit_command(
+                            CONFIGURE_WINDOW
+                                .with(data.get_window_config())
+                                .to(window_id),
+                        );
+                    }
+                }
+            }
+            if let Some(window_handle) = &self.window_handle {
+                window_handle.bring_to_front_and_focus();
+            }
+            Handled::Yes
+        } else if let Some(file_info) = cmd.get(MONITOR_PROFILE_SELECTED) {
+            let path = file_info.path.to_string_lossy().to_string();
+            data.set_monitor_profile_path(Some(path));
+            Handled::Yes
+        } else if let Some(file_info) = cmd.get(PROOFING_PROFILE_SELECTED) {
+            let path = file_info.path.to_string_lossy().to_string();
+            data.set_proofing_profile_path(Some(path));
             Handled::Yes
         } else if cmd.get(TOGGLE_BLUR).is_some() {
             data.blur_enable_toggle();
             Handled::Yes
+        } else if cmd.get(CYCLE_CHANNEL_VIEW).is_some() {
+            if data.has_image() && !data.has_image_error() {
+                data.cycle_channel_view();
+            }
+            Handled::Yes
+        } else if cmd.get(TOGGLE_HISTOGRAM).is_some() {
+            data.histogram_enable_toggle();
+            Handled::Yes
+        } else if let Some((image_timestamp, histogram)) = cmd.get(HISTOGRAM_COMPLETE) {
+            data.set_histogram(image_timestamp, histogram.clone());
+            data.redraw_widgets();
+            Handled::Yes
         } else {
             Handled::No
         }
@@ -118,22 +270,41 @@ impl AppDelegate<AppState> for Delegate {
     fn window_added(
         &mut self,
         id: WindowId,
-        _handle: WindowHandle,
+        handle: WindowHandle,
         data: &mut AppState,
         _env: &Env,
-        _ctx: &mut DelegateCtx,
+        ctx: &mut DelegateCtx,
     ) {
-        platform_api_calls(id);
-        data.set_window_id(id);
+        // The first window is the viewer, the rest are dialogs opened from it
+        if data.get_window_id().is_none() {
+            platform_api_calls(id);
+            accept_dropped_files(&handle, ctx.get_external_handle());
+            data.set_window_id(id);
+            self.window_handle = Some(handle);
+            if !self.settings_errors.is_empty() {
+                let errors = std::mem::take(&mut self.settings_errors);
+                let errors_window = WindowDesc::new(build_settings_errors(errors))
+                    .title("Settings")
+                    .window_size((480., 240.));
+                ctx.new_window(errors_window);
+            }
+        } else if self.preferences_window_id == Some(id) {
+            self.preferences_window_handle = Some(handle);
+        }
     }
 
     fn window_removed(
         &mut self,
-        _id: WindowId,
+        id: WindowId,
         data: &mut AppState,
         _env: &Env,
         _ctx: &mut DelegateCtx,
     ) {
-        data.exit();
+        if data.get_window_id() == Some(id) {
+            data.exit();
+        } else if self.preferences_window_id == Some(id) {
+            self.preferences_window_id = None;
+            self.preferences_window_handle = None;
+        }
     }
 }
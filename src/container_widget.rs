@@ -1,30 +1,44 @@
-use druid::keyboard_types::Key::Character;
 use druid::kurbo::RoundedRect;
 use druid::piet::{InterpolationMode, PietImage};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use druid::widget::prelude::*;
 
-use druid::{Color, Rect, Vec2, WidgetPod};
+use druid::{Color, Rect, Size, TimerToken, Vec2, WidgetPod};
 use druid::{KbKey, Point, Target};
-use druid::{Modifiers, Size};
 
 use crate::app_state::*;
-use crate::commands::{REALSIZE_IMAGE, RECENTER_IMAGE, REDRAW_IMAGE, ZOOM_IMAGE};
+use crate::commands::{
+    DEVELOP_RAW, FULLSCREEN_VIEW, REALSIZE_IMAGE, RECENTER_IMAGE, REDRAW_IMAGE, SCALE_IMAGE,
+    TOGGLE_HISTOGRAM, TOGGLE_SLIDESHOW, ZOOM_IMAGE,
+};
+use crate::hdr_widget::HdrControlsWidget;
+use crate::histogram_widget::HistogramWidget;
 use crate::image_container::ImageState;
 use crate::image_widget::*;
+use crate::key_bindings::KeyAction;
 use crate::toolbar_widget::*;
 
 use crate::osd_widget::{OSDPayload, OSDWidget};
+use crate::recent_widget::RecentWidget;
+use crate::types::ChannelView;
 use crate::types::DisplayState;
-use crate::{LOAD_NEW_IMAGE, NEXT_IMAGE, PREV_IMAGE};
+use crate::{
+    CYCLE_CHANNEL_VIEW, LOAD_NEW_IMAGE, NEXT_IMAGE, NEXT_MIP_LEVEL, NEXT_PAGE, PREV_IMAGE,
+    PREV_MIP_LEVEL, PREV_PAGE, TOGGLE_CUBE_CROSS,
+};
 
 // #[derive(Clone, Data)]
 pub struct ContainerWidget {
     image_widget: WidgetPod<AppState, ImageWidget>,
     toolbar_widget: WidgetPod<AppState, ToolbarWidget>,
     osd_widget: WidgetPod<AppState, OSDWidget>,
+    page_osd_widget: WidgetPod<AppState, OSDWidget>,
+    recent_widget: WidgetPod<AppState, RecentWidget>,
+    histogram_widget: WidgetPod<AppState, HistogramWidget>,
+    hdr_widget: WidgetPod<AppState, HdrControlsWidget>,
     blur_cache: Option<PietImage>,
+    slideshow_timer: Option<TimerToken>,
 }
 
 impl ContainerWidget {
@@ -33,10 +47,29 @@ impl ContainerWidget {
             image_widget: WidgetPod::new(ImageWidget::new()),
             toolbar_widget: WidgetPod::new(ToolbarWidget::new()),
             osd_widget: WidgetPod::new(OSDWidget::new(Size::new(256., 64.))),
+            page_osd_widget: WidgetPod::new(OSDWidget::new(Size::new(400., 36.))),
+            recent_widget: WidgetPod::new(RecentWidget::new(400.)),
+            histogram_widget: WidgetPod::new(HistogramWidget::new(Size::new(288., 160.))),
+            hdr_widget: WidgetPod::new(HdrControlsWidget::new()),
             blur_cache: None,
+            slideshow_timer: None,
         }
     }
 
+    fn paint_blurred_backdrop(ctx: &mut PaintCtx, rect: Rect) {
+        let rect_rounded = RoundedRect::from_rect(rect, 10.);
+        ctx.with_save(|ctx| {
+            ctx.clip(rect_rounded);
+            let blur_capture = ctx.capture_image_area(rect);
+            if let Ok(background_image) = blur_capture {
+                let blurred_background_result = ctx.blur_image(&background_image, 50.);
+                if let Ok(blurred_background) = blurred_background_result {
+                    ctx.draw_image(&blurred_background, rect, InterpolationMode::Bilinear);
+                }
+            }
+        });
+    }
+
     fn paint_osd_blur(&mut self, ctx: &mut PaintCtx, data: &AppState, env: &Env) {
         if data.blur_enabled() {
             let mut container_size = ctx.size();
@@ -44,24 +77,42 @@ impl ContainerWidget {
             let container_rect = container_size.to_rect().inset(11.);
             let osd_size = self.osd_widget.widget().get_size();
             let osd_rect = Rect::from_center_size(container_rect.center(), osd_size);
-            let osd_rect_rounded = RoundedRect::from_rect(osd_rect, 10.);
-            ctx.with_save(|ctx| {
-                ctx.clip(osd_rect_rounded);
-                let osd_blur_capture = ctx.capture_image_area(osd_rect);
-                if let Ok(osd_background_image) = osd_blur_capture {
-                    let osd_blurred_background_result = ctx.blur_image(&osd_background_image, 50.);
-                    if let Ok(osd_blurred_background) = osd_blurred_background_result {
-                        ctx.draw_image(
-                            &osd_blurred_background,
-                            osd_rect,
-                            InterpolationMode::Bilinear,
-                        );
-                    }
-                }
-            });
+            Self::paint_blurred_backdrop(ctx, osd_rect);
         }
         self.osd_widget.paint(ctx, data, env);
     }
+
+    fn paint_page_osd_blur(&mut self, ctx: &mut PaintCtx, data: &AppState, env: &Env) {
+        if data.blur_enabled() {
+            Self::paint_blurred_backdrop(ctx, self.page_osd_widget.layout_rect());
+        }
+        self.page_osd_widget.paint(ctx, data, env);
+    }
+
+    fn paint_recent_blur(&mut self, ctx: &mut PaintCtx, data: &AppState, env: &Env) {
+        if data.blur_enabled() {
+            let recent_rect = Rect::from_origin_size(
+                self.recent_widget.layout_rect().origin(),
+                self.recent_widget.widget().get_size(data),
+            );
+            Self::paint_blurred_backdrop(ctx, recent_rect);
+        }
+        self.recent_widget.paint(ctx, data, env);
+    }
+
+    fn paint_histogram_blur(&mut self, ctx: &mut PaintCtx, data: &AppState, env: &Env) {
+        if data.blur_enabled() {
+            Self::paint_blurred_backdrop(ctx, self.histogram_widget.layout_rect());
+        }
+        self.histogram_widget.paint(ctx, data, env);
+    }
+
+    fn paint_hdr_controls_blur(&mut self, ctx: &mut PaintCtx, data: &AppState, env: &Env) {
+        if data.blur_enabled() {
+            Self::paint_blurred_backdrop(ctx, self.hdr_widget.layout_rect());
+        }
+        self.hdr_widget.paint(ctx, data, env);
+    }
 }
 
 impl Widget<AppState> for ContainerWidget {
@@ -95,6 +146,22 @@ impl Widget<AppState> for ContainerWidget {
                     ctx.request_update();
                     ctx.set_handled();
                 }
+            } else if let Some(scale_factor) = cmd.get(SCALE_IMAGE) {
+                let image_state_guard = data.get_image_ref();
+                let image_state = &mut *image_state_guard.lock().unwrap();
+                if let ImageState::Loaded(image_container) = image_state {
+                    let image_size = image_container.get_size();
+                    let container_size = ctx.size();
+                    let toolbar_height = data.get_toolbar_height();
+                    self.image_widget.widget_mut().scale_image(
+                        image_size,
+                        container_size,
+                        toolbar_height,
+                        *scale_factor,
+                    );
+                    ctx.request_update();
+                    ctx.set_handled();
+                }
             } else if cmd.get(REALSIZE_IMAGE).is_some() {
                 let image_state_guard = data.get_image_ref();
                 let image_state = &mut *image_state_guard.lock().unwrap();
@@ -113,18 +180,47 @@ impl Widget<AppState> for ContainerWidget {
             }
         } else if let Event::KeyDown(k) = event {
             // Key events are always handled here in the container
-            if k.key == KbKey::ArrowRight {
+            let command = match data.get_key_bindings().find_action(k) {
+                Some(KeyAction::NextImage) => Some(NEXT_IMAGE),
+                Some(KeyAction::PrevImage) => Some(PREV_IMAGE),
+                Some(KeyAction::OpenFile) => Some(LOAD_NEW_IMAGE),
+                Some(KeyAction::NextPage) => Some(NEXT_PAGE),
+                Some(KeyAction::PrevPage) => Some(PREV_PAGE),
+                Some(KeyAction::NextMipLevel) => Some(NEXT_MIP_LEVEL),
+                Some(KeyAction::PrevMipLevel) => Some(PREV_MIP_LEVEL),
+                Some(KeyAction::ToggleCubeCross) => Some(TOGGLE_CUBE_CROSS),
+                Some(KeyAction::ToggleHistogram) => Some(TOGGLE_HISTOGRAM),
+                Some(KeyAction::CycleChannelView) => Some(CYCLE_CHANNEL_VIEW),
+                Some(KeyAction::ToggleFullscreen) => Some(FULLSCREEN_VIEW),
+                Some(KeyAction::ToggleSlideshow) => Some(TOGGLE_SLIDESHOW),
+                Some(KeyAction::DevelopRaw) => Some(DEVELOP_RAW),
+                None if k.key == KbKey::Escape && data.fullscreen_enabled() => {
+                    Some(FULLSCREEN_VIEW)
+                }
+                None => None,
+            };
+            if let Some(command) = command {
+                event_sink
+                    .submit_command(command, Instant::now(), Target::Auto)
+                    .expect("Failed to send key command");
+            }
+            // Escape also ends a running slideshow
+            if k.key == KbKey::Escape {
+                data.set_slideshow_interval(None);
+                self.slideshow_timer = None;
+            }
+        } else if let Event::Timer(token) = event {
+            if self.slideshow_timer == Some(*token) {
                 event_sink
                     .submit_command(NEXT_IMAGE, Instant::now(), Target::Auto)
                     .expect("Failed to send load next image command");
-            } else if k.key == KbKey::ArrowLeft {
-                event_sink
-                    .submit_command(PREV_IMAGE, Instant::now(), Target::Auto)
-                    .expect("Failed to send load previous image command");
-            } else if k.key == Character(String::from('o')) && k.mods == Modifiers::CONTROL {
-                event_sink
-                    .submit_command(LOAD_NEW_IMAGE, Instant::now(), Target::Auto)
-                    .expect("Failed to send load new image command");
+                self.slideshow_timer = data
+                    .slideshow_interval()
+                    .map(|interval| ctx.request_timer(Duration::from_secs_f64(interval)));
+                ctx.set_handled();
+            } else {
+                self.image_widget.event(ctx, event, data, env);
+                self.toolbar_widget.event(ctx, event, data, env);
             }
         } else if let Event::MouseDown(e)
         | Event::MouseUp(e)
@@ -133,10 +229,21 @@ impl Widget<AppState> for ContainerWidget {
         {
             if !data.has_image() {
                 self.osd_widget.event(ctx, event, data, env);
+                self.recent_widget.event(ctx, event, data, env);
+            }
+
+            // The HDR controls float above the image, so they get the first look at mouse events
+            let hdr_controls_visible = data.has_hdr_image();
+            let is_over_hdr_controls = hdr_controls_visible
+                && (self.hdr_widget.layout_rect().contains(e.pos) || self.hdr_widget.is_active());
+            if hdr_controls_visible {
+                self.hdr_widget.event(ctx, event, data, env);
             }
 
             // Mouse events will be handled by either the toolbar or the image widget
-            if e.window_pos.y < ctx.size().height - data.get_toolbar_height() {
+            if is_over_hdr_controls {
+                // Already delivered to the HDR controls above
+            } else if e.window_pos.y < ctx.size().height - data.get_toolbar_height() {
                 ctx.set_focus(self.image_widget.id());
                 self.image_widget.event(ctx, event, data, env);
 
@@ -155,7 +262,12 @@ impl Widget<AppState> for ContainerWidget {
             self.image_widget.event(ctx, event, data, env);
             self.toolbar_widget.event(ctx, event, data, env);
         } else if let Event::WindowConnected = event {
-        } else if let Event::WindowSize(_e) = event {
+            if let Some(interval) = data.slideshow_interval() {
+                self.slideshow_timer = Some(ctx.request_timer(Duration::from_secs_f64(interval)));
+            }
+        } else if let Event::WindowSize(_) | Event::WindowCloseRequested = event {
+            // Windows don't report being moved, so the position is taken along with the size
+            data.record_window_geometry(ctx.window());
         } else {
             self.image_widget.event(ctx, event, data, env);
             self.toolbar_widget.event(ctx, event, data, env);
@@ -174,10 +286,26 @@ impl Widget<AppState> for ContainerWidget {
         self.toolbar_widget.lifecycle(_ctx, _event, _data, _env);
 
         self.osd_widget.lifecycle(_ctx, _event, _data, _env);
+
+        self.page_osd_widget.lifecycle(_ctx, _event, _data, _env);
+
+        self.recent_widget.lifecycle(_ctx, _event, _data, _env);
+
+        self.histogram_widget.lifecycle(_ctx, _event, _data, _env);
+
+        self.hdr_widget.lifecycle(_ctx, _event, _data, _env);
     }
 
     fn update(&mut self, _ctx: &mut UpdateCtx, _old_data: &AppState, data: &AppState, _env: &Env) {
         self.toolbar_widget.update(_ctx, data, _env);
+        self.hdr_widget.update(_ctx, data, _env);
+
+        // A slideshow can also be started by a launch forwarded from another process
+        if data.slideshow_interval() != _old_data.slideshow_interval() {
+            self.slideshow_timer = data
+                .slideshow_interval()
+                .map(|interval| _ctx.request_timer(Duration::from_secs_f64(interval)));
+        }
 
         let needs_paint = true; // repaint on all updates, for now
 
@@ -193,7 +321,10 @@ impl Widget<AppState> for ContainerWidget {
         // }
 
         if needs_paint {
-            let new_window_title = String::from("Foxfire - ") + &data.get_image_name();
+            let mut new_window_title = String::from("Foxfire - ") + &data.get_image_name();
+            if data.get_channel_view() != ChannelView::Composite {
+                new_window_title = new_window_title + " [" + data.get_channel_view().label() + "]";
+            }
             _ctx.window().set_title(&new_window_title);
             self.blur_cache = None;
             _ctx.children_changed();
@@ -238,6 +369,38 @@ impl Widget<AppState> for ContainerWidget {
         self.osd_widget
             .set_origin(_layout_ctx, _data, _env, osd_widget_origin);
 
+        let recent_size = self
+            .recent_widget
+            .layout(_layout_ctx, &bc.loosen(), _data, _env);
+        let recent_origin = Point::new(
+            bc.max().width / 2.0 - recent_size.width / 2.0,
+            osd_widget_origin.y + osd_widget_size.height + 16.,
+        );
+        self.recent_widget
+            .set_origin(_layout_ctx, _data, _env, recent_origin);
+
+        let page_osd_size = self
+            .page_osd_widget
+            .layout(_layout_ctx, &bc.loosen(), _data, _env);
+        let page_osd_origin = Point::new(
+            bc.max().width / 2.0 - page_osd_size.width / 2.0,
+            bc.max().height - toolbar_height - page_osd_size.height - 12.,
+        );
+        self.page_osd_widget
+            .set_origin(_layout_ctx, _data, _env, page_osd_origin);
+
+        let histogram_size = self
+            .histogram_widget
+            .layout(_layout_ctx, &bc.loosen(), _data, _env);
+        let histogram_origin = Point::new(bc.max().width - histogram_size.width - 12., 12.);
+        self.histogram_widget
+            .set_origin(_layout_ctx, _data, _env, histogram_origin);
+
+        self.hdr_widget
+            .layout(_layout_ctx, &bc.loosen(), _data, _env);
+        self.hdr_widget
+            .set_origin(_layout_ctx, _data, _env, Point::new(12., 12.));
+
         if bc.is_width_bounded() && bc.is_height_bounded() {
             bc.max()
         } else {
@@ -296,6 +459,22 @@ impl Widget<AppState> for ContainerWidget {
 
         self.toolbar_widget.paint(ctx, data, env);
 
+        if data.histogram_enabled() && data.has_image() && !data.has_image_error() {
+            self.paint_histogram_blur(ctx, data, env);
+        }
+
+        if data.has_hdr_image() {
+            self.paint_hdr_controls_blur(ctx, data, env);
+        }
+
+        // Indicate the position within multi-page files and texture mip chains
+        if let Some(page_description) = data.get_page_description() {
+            let stroke_color = Color::rgb8(129, 161, 193);
+            let page_payload = OSDPayload::new(None, page_description, 14., stroke_color);
+            self.page_osd_widget.widget_mut().set_payload(page_payload);
+            self.paint_page_osd_blur(ctx, data, env);
+        }
+
         // Paint the load button if there is no loaded image and we're not loading
         if !data.has_image() && !data.get_loading_state() {
             let stroke_color = Color::rgb8(136, 192, 208);
@@ -306,7 +485,10 @@ impl Widget<AppState> for ContainerWidget {
                 stroke_color,
             );
             self.osd_widget.widget_mut().set_payload(load_file_payload);
-            self.paint_osd_blur(ctx, data, env)
+            self.paint_osd_blur(ctx, data, env);
+            if !data.get_recent_paths().is_empty() {
+                self.paint_recent_blur(ctx, data, env);
+            }
         }
         // If we're loading an image, paint the loading display
         else if data.get_loading_state() {
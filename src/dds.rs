@@ -0,0 +1,471 @@
+use std::error::Error;
+
+use image::{DynamicImage, Rgb32FImage, RgbaImage};
+
+use crate::image_loader::{DecodedImage, ImagePage, TextureInfo};
+
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+const HEADER_SIZE: usize = 128;
+const DX10_HEADER_SIZE: usize = 20;
+/// The largest texture Direct3D allows, which keeps every size computation far from overflowing
+const MAX_DIMENSION: u32 = 16384;
+
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_VOLUME: u32 = 0x200000;
+const DDS_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+const CUBE_FACE_NAMES: [&str; 6] = ["+X", "-X", "+Y", "-Y", "+Z", "-Z"];
+
+#[derive(Clone, Copy, PartialEq)]
+enum PixelFormat {
+    Bc1,
+    Bc2,
+    Bc3,
+    Bc4 { signed: bool },
+    Bc5 { signed: bool },
+    Bc6h { signed: bool },
+    Bc7,
+    Rgba8,
+    Bgra8,
+    Bgrx8,
+}
+
+impl PixelFormat {
+    fn from_four_cc(four_cc: &[u8]) -> Option<Self> {
+        match four_cc {
+            b"DXT1" => Some(PixelFormat::Bc1),
+            b"DXT2" | b"DXT3" => Some(PixelFormat::Bc2),
+            b"DXT4" | b"DXT5" => Some(PixelFormat::Bc3),
+            b"ATI1" | b"BC4U" => Some(PixelFormat::Bc4 { signed: false }),
+            b"BC4S" => Some(PixelFormat::Bc4 { signed: true }),
+            b"ATI2" | b"BC5U" => Some(PixelFormat::Bc5 { signed: false }),
+            b"BC5S" => Some(PixelFormat::Bc5 { signed: true }),
+            _ => None,
+        }
+    }
+
+    fn from_dxgi_format(dxgi_format: u32) -> Option<Self> {
+        match dxgi_format {
+            27..=29 => Some(PixelFormat::Rgba8),
+            70..=72 => Some(PixelFormat::Bc1),
+            73..=75 => Some(PixelFormat::Bc2),
+            76..=78 => Some(PixelFormat::Bc3),
+            79 | 80 => Some(PixelFormat::Bc4 { signed: false }),
+            81 => Some(PixelFormat::Bc4 { signed: true }),
+            82 | 83 => Some(PixelFormat::Bc5 { signed: false }),
+            84 => Some(PixelFormat::Bc5 { signed: true }),
+            87 | 90 | 91 => Some(PixelFormat::Bgra8),
+            88 | 92 | 93 => Some(PixelFormat::Bgrx8),
+            94 | 95 => Some(PixelFormat::Bc6h { signed: false }),
+            96 => Some(PixelFormat::Bc6h { signed: true }),
+            97..=99 => Some(PixelFormat::Bc7),
+            _ => None,
+        }
+    }
+
+    fn from_bit_masks(bit_count: u32, red_mask: u32, alpha_mask: u32) -> Option<Self> {
+        match (bit_count, red_mask, alpha_mask) {
+            (32, 0x000000ff, _) => Some(PixelFormat::Rgba8),
+            (32, 0x00ff0000, 0) => Some(PixelFormat::Bgrx8),
+            (32, 0x00ff0000, _) => Some(PixelFormat::Bgra8),
+            _ => None,
+        }
+    }
+
+    fn get_name(&self) -> &'static str {
+        match self {
+            PixelFormat::Bc1 => "BC1 (DXT1)",
+            PixelFormat::Bc2 => "BC2 (DXT3)",
+            PixelFormat::Bc3 => "BC3 (DXT5)",
+            PixelFormat::Bc4 { signed: false } => "BC4 unsigned",
+            PixelFormat::Bc4 { signed: true } => "BC4 signed",
+            PixelFormat::Bc5 { signed: false } => "BC5 unsigned",
+            PixelFormat::Bc5 { signed: true } => "BC5 signed",
+            PixelFormat::Bc6h { signed: false } => "BC6H unsigned float",
+            PixelFormat::Bc6h { signed: true } => "BC6H signed float",
+            PixelFormat::Bc7 => "BC7",
+            PixelFormat::Rgba8 => "RGBA8",
+            PixelFormat::Bgra8 => "BGRA8",
+            PixelFormat::Bgrx8 => "BGRX8",
+        }
+    }
+
+    /// The size of one 4×4 block, or of one pixel for uncompressed formats
+    fn get_block_size(&self) -> usize {
+        match self {
+            PixelFormat::Bc1 | PixelFormat::Bc4 { .. } => 8,
+            PixelFormat::Rgba8 | PixelFormat::Bgra8 | PixelFormat::Bgrx8 => 4,
+            _ => 16,
+        }
+    }
+
+    fn is_compressed(&self) -> bool {
+        !matches!(
+            self,
+            PixelFormat::Rgba8 | PixelFormat::Bgra8 | PixelFormat::Bgrx8
+        )
+    }
+
+    fn get_level_size(&self, width: u32, height: u32) -> Option<usize> {
+        let (units_wide, units_high) = if self.is_compressed() {
+            ((width as usize + 3) / 4, (height as usize + 3) / 4)
+        } else {
+            (width as usize, height as usize)
+        };
+        units_wide
+            .checked_mul(units_high)?
+            .checked_mul(self.get_block_size())
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+/// Decodes every mip level of every array slice and cube face in a DDS file
+pub fn decode_dds(data: &[u8]) -> Result<DecodedImage, Box<dyn Error>> {
+    if data.len() < HEADER_SIZE || &data[0..4] != DDS_MAGIC {
+        return Err("Not a DDS file".into());
+    }
+
+    let flags = read_u32(data, 8);
+    let height = read_u32(data, 12);
+    let width = read_u32(data, 16);
+    if width == 0 || height == 0 {
+        return Err("DDS image has no pixels".into());
+    }
+    if width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return Err(format!(
+            "DDS images larger than {0}×{0} are not supported",
+            MAX_DIMENSION
+        )
+        .into());
+    }
+    // A chain can't go past the 1×1 level, however many the header claims
+    let mip_count = if flags & DDSD_MIPMAPCOUNT != 0 {
+        read_u32(data, 28)
+            .min(32 - width.max(height).leading_zeros())
+            .max(1)
+    } else {
+        1
+    };
+    let pixel_format_flags = read_u32(data, 80);
+    let four_cc = &data[84..88];
+    let caps2 = read_u32(data, 112);
+
+    if caps2 & DDSCAPS2_VOLUME != 0 {
+        return Err("Volume textures are not supported".into());
+    }
+
+    let mut data_offset = HEADER_SIZE;
+    let mut face_count = if caps2 & DDSCAPS2_CUBEMAP != 0 { 6 } else { 1 };
+    let mut slice_count = 1;
+    let pixel_format = if pixel_format_flags & DDPF_FOURCC != 0 && four_cc == b"DX10" {
+        if data.len() < HEADER_SIZE + DX10_HEADER_SIZE {
+            return Err("Truncated DX10 header".into());
+        }
+        let dxgi_format = read_u32(data, HEADER_SIZE);
+        let misc_flag = read_u32(data, HEADER_SIZE + 8);
+        slice_count = read_u32(data, HEADER_SIZE + 12).max(1) as usize;
+        if misc_flag & DDS_RESOURCE_MISC_TEXTURECUBE != 0 {
+            face_count = 6;
+        }
+        data_offset += DX10_HEADER_SIZE;
+        PixelFormat::from_dxgi_format(dxgi_format)
+    } else if pixel_format_flags & DDPF_FOURCC != 0 {
+        PixelFormat::from_four_cc(four_cc)
+    } else if pixel_format_flags & DDPF_RGB != 0 {
+        PixelFormat::from_bit_masks(read_u32(data, 88), read_u32(data, 92), read_u32(data, 104))
+    } else {
+        None
+    }
+    .ok_or("Unsupported DDS pixel format")?;
+
+    // Every surface holds at least its top level, so a bogus count is caught before reserving
+    let top_level_size = pixel_format
+        .get_level_size(width, height)
+        .ok_or("DDS image is too large")?;
+    let surface_count = slice_count.saturating_mul(face_count);
+    if surface_count > (data.len() - data_offset) / top_level_size {
+        return Err("Truncated DDS surface data".into());
+    }
+
+    // Surfaces are stored slice by slice, each face with its full mip chain
+    let mut pages = Vec::with_capacity(surface_count);
+    for slice in 0..slice_count {
+        for face in 0..face_count {
+            let mut levels = Vec::with_capacity(mip_count as usize);
+            for level in 0..mip_count {
+                let level_width = (width >> level).max(1);
+                let level_height = (height >> level).max(1);
+                let level_size = pixel_format
+                    .get_level_size(level_width, level_height)
+                    .ok_or("DDS image is too large")?;
+                let level_end = data_offset
+                    .checked_add(level_size)
+                    .ok_or("Truncated DDS surface data")?;
+                let level_data = data
+                    .get(data_offset..level_end)
+                    .ok_or("Truncated DDS surface data")?;
+                levels.push(decode_surface(
+                    level_data,
+                    pixel_format,
+                    level_width,
+                    level_height,
+                ));
+                data_offset = level_end;
+            }
+
+            let label = match (slice_count > 1, face_count > 1) {
+                (true, true) => Some(format!(
+                    "Slice {} · Face {}",
+                    slice + 1,
+                    CUBE_FACE_NAMES[face]
+                )),
+                (true, false) => Some(format!("Slice {}", slice + 1)),
+                (false, true) => Some(format!("Face {}", CUBE_FACE_NAMES[face])),
+                (false, false) => None,
+            };
+            let mut levels = levels.into_iter();
+            pages.push(ImagePage {
+                image: levels.next().unwrap(),
+                label,
+                mip_levels: levels.collect(),
+            });
+        }
+    }
+
+    Ok(DecodedImage {
+        pages,
+        page_index: 0,
+        texture_info: Some(TextureInfo {
+            format_name: pixel_format.get_name().to_string(),
+            face_count,
+        }),
+        icc_profile: None,
+        vector_source: None,
+        raw_preview: false,
+    })
+}
+
+fn decode_surface(data: &[u8], pixel_format: PixelFormat, width: u32, height: u32) -> DynamicImage {
+    match pixel_format {
+        PixelFormat::Rgba8 | PixelFormat::Bgra8 | PixelFormat::Bgrx8 => {
+            let mut rgba = RgbaImage::new(width, height);
+            for (pixel, source) in rgba.pixels_mut().zip(data.chunks_exact(4)) {
+                pixel.0 = match pixel_format {
+                    PixelFormat::Rgba8 => [source[0], source[1], source[2], source[3]],
+                    PixelFormat::Bgra8 => [source[2], source[1], source[0], source[3]],
+                    _ => [source[2], source[1], source[0], 255],
+                };
+            }
+            DynamicImage::ImageRgba8(rgba)
+        }
+        PixelFormat::Bc6h { signed } => {
+            // Blocks are decoded into a buffer padded to whole blocks, then cropped
+            let padded_width = (width + 3) / 4 * 4;
+            let padded_height = (height + 3) / 4 * 4;
+            let pitch = padded_width as usize * 3;
+            let mut buffer = vec![0f32; pitch * padded_height as usize];
+            for (block_index, block) in data.chunks_exact(16).enumerate() {
+                let block_x = block_index % (padded_width as usize / 4);
+                let block_y = block_index / (padded_width as usize / 4);
+                let offset = block_y * 4 * pitch + block_x * 4 * 3;
+                bcdec_rs::bc6h_float(block, &mut buffer[offset..], pitch, signed);
+            }
+            let padded = Rgb32FImage::from_raw(padded_width, padded_height, buffer).unwrap();
+            DynamicImage::ImageRgb32F(padded).crop_imm(0, 0, width, height)
+        }
+        _ => {
+            let padded_width = (width + 3) / 4 * 4;
+            let padded_height = (height + 3) / 4 * 4;
+            let mut padded = RgbaImage::new(padded_width, padded_height);
+            let block_size = pixel_format.get_block_size();
+            for (block_index, block) in data.chunks_exact(block_size).enumerate() {
+                let block_x = (block_index % (padded_width as usize / 4)) as u32 * 4;
+                let block_y = (block_index / (padded_width as usize / 4)) as u32 * 4;
+                let texels = decode_block(block, pixel_format);
+                for (texel_index, texel) in texels.iter().enumerate() {
+                    let x = block_x + texel_index as u32 % 4;
+                    let y = block_y + texel_index as u32 / 4;
+                    padded.get_pixel_mut(x, y).0 = *texel;
+                }
+            }
+            DynamicImage::ImageRgba8(padded).crop_imm(0, 0, width, height)
+        }
+    }
+}
+
+/// Decodes one 4×4 block into row-major RGBA texels
+fn decode_block(block: &[u8], pixel_format: PixelFormat) -> [[u8; 4]; 16] {
+    let mut texels = [[0, 0, 0, 255]; 16];
+    match pixel_format {
+        PixelFormat::Bc1 => decode_color_block(block, &mut texels, true),
+        PixelFormat::Bc2 => {
+            decode_color_block(&block[8..16], &mut texels, false);
+            for (index, texel) in texels.iter_mut().enumerate() {
+                let alpha = (block[index / 2] >> ((index % 2) * 4)) & 0x0f;
+                texel[3] = alpha * 17;
+            }
+        }
+        PixelFormat::Bc3 => {
+            decode_color_block(&block[8..16], &mut texels, false);
+            let alpha = decode_alpha_block(&block[0..8], false);
+            for (texel, alpha) in texels.iter_mut().zip(alpha) {
+                texel[3] = alpha;
+            }
+        }
+        PixelFormat::Bc4 { signed } => {
+            let red = decode_alpha_block(&block[0..8], signed);
+            for (texel, red) in texels.iter_mut().zip(red) {
+                *texel = [red, red, red, 255];
+            }
+        }
+        PixelFormat::Bc5 { signed } => {
+            let red = decode_alpha_block(&block[0..8], signed);
+            let green = decode_alpha_block(&block[8..16], signed);
+            for (index, texel) in texels.iter_mut().enumerate() {
+                *texel = [red[index], green[index], 0, 255];
+            }
+        }
+        PixelFormat::Bc7 => {
+            let mut decoded = [0u8; 16 * 4];
+            bcdec_rs::bc7(block, &mut decoded, 4 * 4);
+            for (texel, source) in texels.iter_mut().zip(decoded.chunks_exact(4)) {
+                *texel = [source[0], source[1], source[2], source[3]];
+            }
+        }
+        _ => {}
+    }
+    texels
+}
+
+fn decode_color_block(block: &[u8], texels: &mut [[u8; 4]; 16], allow_transparency: bool) {
+    let expand_565 = |color: u16| -> [u32; 3] {
+        let r = ((color >> 11) & 0x1f) as u32;
+        let g = ((color >> 5) & 0x3f) as u32;
+        let b = (color & 0x1f) as u32;
+        [
+            (r << 3) | (r >> 2),
+            (g << 2) | (g >> 4),
+            (b << 3) | (b >> 2),
+        ]
+    };
+    let color0 = u16::from_le_bytes([block[0], block[1]]);
+    let color1 = u16::from_le_bytes([block[2], block[3]]);
+    let [r0, g0, b0] = expand_565(color0);
+    let [r1, g1, b1] = expand_565(color1);
+
+    let mut palette = [[0u8; 4]; 4];
+    palette[0] = [r0 as u8, g0 as u8, b0 as u8, 255];
+    palette[1] = [r1 as u8, g1 as u8, b1 as u8, 255];
+    if color0 > color1 || !allow_transparency {
+        let mix = |a: u32, b: u32| ((2 * a + b) / 3) as u8;
+        palette[2] = [mix(r0, r1), mix(g0, g1), mix(b0, b1), 255];
+        palette[3] = [mix(r1, r0), mix(g1, g0), mix(b1, b0), 255];
+    } else {
+        let mix = |a: u32, b: u32| ((a + b) / 2) as u8;
+        palette[2] = [mix(r0, r1), mix(g0, g1), mix(b0, b1), 255];
+        palette[3] = [0, 0, 0, 0];
+    }
+
+    let indices = read_u32(block, 4);
+    for (index, texel) in texels.iter_mut().enumerate() {
+        let palette_index = (indices >> (index * 2)) & 0x3;
+        *texel = palette[palette_index as usize];
+    }
+}
+
+/// Decodes the interpolated single channel blocks shared by BC3 alpha, BC4 and BC5
+fn decode_alpha_block(block: &[u8], signed: bool) -> [u8; 16] {
+    // Signed endpoints are remapped from [-127, 127] to [0, 254] so the same math applies
+    let to_unsigned = |value: u8| -> i32 {
+        if signed {
+            (value as i8).max(-127) as i32 + 127
+        } else {
+            value as i32
+        }
+    };
+    let alpha0 = to_unsigned(block[0]);
+    let alpha1 = to_unsigned(block[1]);
+    let maximum = if signed { 254 } else { 255 };
+
+    let mut palette = [0i32; 8];
+    palette[0] = alpha0;
+    palette[1] = alpha1;
+    if alpha0 > alpha1 {
+        for (index, value) in palette.iter_mut().enumerate().skip(2) {
+            let weight = index as i32 - 1;
+            *value = ((7 - weight) * alpha0 + weight * alpha1) / 7;
+        }
+    } else {
+        for (index, value) in palette.iter_mut().enumerate().take(6).skip(2) {
+            let weight = index as i32 - 1;
+            *value = ((5 - weight) * alpha0 + weight * alpha1) / 5;
+        }
+        palette[6] = 0;
+        palette[7] = maximum;
+    }
+
+    let mut index_bits: u64 = 0;
+    for (byte_index, byte) in block[2..8].iter().enumerate() {
+        index_bits |= (*byte as u64) << (8 * byte_index);
+    }
+
+    let mut values = [0u8; 16];
+    for (index, value) in values.iter_mut().enumerate() {
+        let palette_index = ((index_bits >> (index * 3)) & 0x7) as usize;
+        let channel_value = palette[palette_index];
+        *value = if signed {
+            (channel_value * 255 / 254) as u8
+        } else {
+            channel_value as u8
+        };
+    }
+    values
+}
+
+/// Lays out the six faces of a cube map as a horizontal cross:
+///
+/// ```text
+///       +Y
+///   -X  +Z  +X  -Z
+///       -Y
+/// ```
+pub fn compose_cube_cross(faces: &[&DynamicImage]) -> DynamicImage {
+    const FACE_POSITIONS: [(i64, i64); 6] = [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)];
+    let face_width = faces[0].width();
+    let face_height = faces[0].height();
+    let is_hdr = matches!(
+        faces[0],
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+    );
+
+    let mut cross = if is_hdr {
+        DynamicImage::ImageRgba32F(image::Rgba32FImage::new(face_width * 4, face_height * 3))
+    } else {
+        DynamicImage::new_rgba8(face_width * 4, face_height * 3)
+    };
+    for (face, (column, row)) in faces.iter().zip(FACE_POSITIONS) {
+        let face_image = if is_hdr {
+            DynamicImage::ImageRgba32F(face.to_rgba32f())
+        } else {
+            DynamicImage::ImageRgba8(face.to_rgba8())
+        };
+        image::imageops::replace(
+            &mut cross,
+            &face_image,
+            column * face_width as i64,
+            row * face_height as i64,
+        );
+    }
+    cross
+}
@@ -0,0 +1,46 @@
+use std::io::Cursor;
+
+use exif::{Exif, In, Reader, Tag};
+use image::DynamicImage;
+
+/// Reads the EXIF block of a JPEG, TIFF, HEIF, PNG or WebP file
+pub fn read_exif(data: &[u8]) -> Option<Exif> {
+    Reader::new()
+        .read_from_container(&mut Cursor::new(data))
+        .ok()
+}
+
+/// Lists every EXIF field of the primary image as a tag name and a readable value
+pub fn describe_exif(exif: &Exif) -> Vec<(String, String)> {
+    exif.fields()
+        .filter(|field| field.ifd_num == In::PRIMARY)
+        .map(|field| {
+            (
+                field.tag.to_string(),
+                field.display_value().with_unit(exif).to_string(),
+            )
+        })
+        .collect()
+}
+
+/// The EXIF orientation, from 1 (upright) to 8
+pub fn get_orientation(exif: &Exif) -> u32 {
+    exif.get_field(Tag::Orientation, In::PRIMARY)
+        .and_then(|field| field.value.get_uint(0))
+        .filter(|orientation| (1..=8).contains(orientation))
+        .unwrap_or(1)
+}
+
+/// Turns an image stored in the given EXIF orientation upright
+pub fn apply_orientation(image: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
@@ -0,0 +1,132 @@
+//! Decoders for formats the image crate doesn't cover, each behind its own cargo feature
+
+use std::error::Error;
+use std::path::Path;
+
+use crate::image_loader::DecodedImage;
+
+#[cfg(feature = "jxl")]
+const JXL_CODESTREAM_MAGIC: &[u8] = &[0xFF, 0x0A];
+#[cfg(feature = "jxl")]
+const JXL_CONTAINER_MAGIC: &[u8] = &[
+    0, 0, 0, 0x0C, b'J', b'X', b'L', b' ', 0x0D, 0x0A, 0x87, 0x0A,
+];
+
+/// Picks a decoder by extension or magic number, or returns None to use the image crate
+#[allow(unused_variables)]
+pub fn decode_extended_format(
+    path: &Path,
+    data: &[u8],
+) -> Option<Result<DecodedImage, Box<dyn Error>>> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    #[cfg(feature = "qoi")]
+    if extension == "qoi" || data.starts_with(b"qoif") {
+        return Some(decode_qoi(data));
+    }
+    #[cfg(feature = "jxl")]
+    if extension == "jxl"
+        || data.starts_with(JXL_CODESTREAM_MAGIC)
+        || data.starts_with(JXL_CONTAINER_MAGIC)
+    {
+        return Some(decode_jxl(data));
+    }
+    #[cfg(feature = "heif")]
+    if extension == "heic" || extension == "heif" {
+        return Some(decode_heif(data));
+    }
+    None
+}
+
+#[cfg(feature = "qoi")]
+pub fn decode_qoi(data: &[u8]) -> Result<DecodedImage, Box<dyn Error>> {
+    use image::{DynamicImage, RgbImage, RgbaImage};
+
+    let (header, pixels) = qoi::decode_to_vec(data)?;
+    let image = match header.channels {
+        qoi::Channels::Rgb => {
+            RgbImage::from_raw(header.width, header.height, pixels).map(DynamicImage::ImageRgb8)
+        }
+        qoi::Channels::Rgba => {
+            RgbaImage::from_raw(header.width, header.height, pixels).map(DynamicImage::ImageRgba8)
+        }
+    }
+    .ok_or("QOI pixel data doesn't match its header")?;
+    Ok(DecodedImage::from_image(image))
+}
+
+#[cfg(feature = "jxl")]
+pub fn decode_jxl(data: &[u8]) -> Result<DecodedImage, Box<dyn Error>> {
+    use image::{DynamicImage, Rgba, Rgba32FImage};
+    use std::io::Cursor;
+
+    let jxl_image = jxl_oxide::JxlImage::builder().read(Cursor::new(data))?;
+    let render = jxl_image.render_frame(0)?;
+    let frame_buffer = render.image_all_channels();
+    let (width, height) = (frame_buffer.width() as u32, frame_buffer.height() as u32);
+    let channels = frame_buffer.channels();
+    let samples = frame_buffer.buf();
+
+    let hdr_image = Rgba32FImage::from_fn(width, height, |x, y| {
+        let offset = (y as usize * width as usize + x as usize) * channels;
+        let sample = |channel: usize| samples[offset + channel];
+        Rgba(match channels {
+            1 => [sample(0), sample(0), sample(0), 1.],
+            2 => [sample(0), sample(0), sample(0), sample(1)],
+            3 => [sample(0), sample(1), sample(2), 1.],
+            _ => [sample(0), sample(1), sample(2), sample(3)],
+        })
+    });
+
+    // Only keep floating point data when the image actually goes past the display range, so
+    // ordinary images don't bring up the HDR controls
+    let is_hdr = samples.iter().any(|sample| *sample > 1.);
+    let image = if is_hdr {
+        DynamicImage::ImageRgba32F(hdr_image)
+    } else {
+        DynamicImage::ImageRgba8(DynamicImage::ImageRgba32F(hdr_image).to_rgba8())
+    };
+    Ok(DecodedImage::from_image(image))
+}
+
+/// Decodes every top-level image of a HEIF container, starting at the primary one. Builds of
+/// libheif with an AV1 decoder handle AVIF this way too.
+#[cfg(feature = "heif")]
+pub fn decode_heif(data: &[u8]) -> Result<DecodedImage, Box<dyn Error>> {
+    use image::{DynamicImage, RgbaImage};
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+    use crate::image_loader::ImagePage;
+
+    let lib_heif = LibHeif::new();
+    let context = HeifContext::read_from_bytes(data)?;
+    let mut pages = Vec::new();
+    let mut primary_index = 0;
+    for (index, handle) in context.top_level_image_handles().iter().enumerate() {
+        if handle.is_primary() {
+            primary_index = index;
+        }
+        let heif_image = lib_heif.decode(handle, ColorSpace::Rgb(RgbChroma::Rgba), None)?;
+        let plane = heif_image
+            .planes()
+            .interleaved
+            .ok_or("HEIF image has no interleaved plane")?;
+
+        // Rows may be padded, so copy them one at a time
+        let row_length = plane.width as usize * 4;
+        let mut pixels = Vec::with_capacity(row_length * plane.height as usize);
+        for row in plane.data.chunks(plane.stride).take(plane.height as usize) {
+            pixels.extend_from_slice(&row[..row_length]);
+        }
+        let image = RgbaImage::from_raw(plane.width, plane.height, pixels)
+            .ok_or("HEIF pixel data doesn't match its size")?;
+        pages.push(ImagePage::new(DynamicImage::ImageRgba8(image), None));
+    }
+
+    let mut decoded_image = DecodedImage::from_pages(pages).ok_or("HEIF file has no images")?;
+    decoded_image.page_index = primary_index;
+    Ok(decoded_image)
+}
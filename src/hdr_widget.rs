@@ -0,0 +1,129 @@
+use druid::kurbo::RoundedRect;
+use druid::widget::prelude::*;
+use druid::widget::{Button, CrossAxisAlignment, EnvScope, Flex, Label, RadioGroup, Slider};
+use druid::{lens, theme, Color, Point, WidgetExt, WidgetPod};
+
+use crate::app_state::AppState;
+use crate::types::ToneMapping;
+
+const MIN_EXPOSURE: f64 = -10.;
+const MAX_EXPOSURE: f64 = 10.;
+const MIN_GAMMA: f64 = 1.;
+const MAX_GAMMA: f64 = 3.;
+
+/// Exposure, gamma and tone mapping controls, shown while a floating point image is loaded
+pub struct HdrControlsWidget {
+    controls: WidgetPod<AppState, Box<dyn Widget<AppState>>>,
+}
+
+impl HdrControlsWidget {
+    pub fn new() -> Self {
+        let exposure_label = Label::new(|data: &AppState, _env: &Env| {
+            format!("Exposure: {:+.1} EV", data.get_exposure())
+        });
+        let exposure_slider = Slider::new()
+            .with_range(MIN_EXPOSURE, MAX_EXPOSURE)
+            .lens(lens::Map::new(
+                |data: &AppState| data.get_exposure(),
+                |data: &mut AppState, exposure| data.set_exposure(exposure),
+            ))
+            .expand_width();
+
+        let gamma_label =
+            Label::new(|data: &AppState, _env: &Env| format!("Gamma: {:.2}", data.get_gamma()));
+        let gamma_slider = Slider::new()
+            .with_range(MIN_GAMMA, MAX_GAMMA)
+            .lens(lens::Map::new(
+                |data: &AppState| data.get_gamma(),
+                |data: &mut AppState, gamma| data.set_gamma(gamma),
+            ))
+            .expand_width();
+
+        let tone_mapping_choices = RadioGroup::row(
+            ToneMapping::ALL
+                .iter()
+                .map(|tone_mapping| (tone_mapping.label(), *tone_mapping)),
+        )
+        .lens(lens::Map::new(
+            |data: &AppState| data.get_tone_mapping(),
+            |data: &mut AppState, tone_mapping| data.set_tone_mapping(tone_mapping),
+        ));
+
+        let dynamic_range_label =
+            Label::new(|data: &AppState, _env: &Env| data.get_dynamic_range_description());
+
+        let reset_button = Button::new("Reset").on_click(|_ctx, data: &mut AppState, _env| {
+            data.set_exposure(0.);
+            data.set_gamma(2.2);
+        });
+
+        let controls = Flex::column()
+            .cross_axis_alignment(CrossAxisAlignment::Start)
+            .with_child(exposure_label)
+            .with_child(exposure_slider)
+            .with_spacer(4.)
+            .with_child(gamma_label)
+            .with_child(gamma_slider)
+            .with_spacer(4.)
+            .with_child(tone_mapping_choices)
+            .with_spacer(4.)
+            .with_child(dynamic_range_label)
+            .with_spacer(4.)
+            .with_child(reset_button)
+            .padding(12.)
+            .fix_width(320.);
+
+        // Match the label colors to the light or dark backdrop
+        let themed_controls = EnvScope::new(
+            |env: &mut Env, data: &AppState| {
+                if !data.dark_theme_enabled {
+                    env.set(theme::TEXT_COLOR, Color::BLACK);
+                }
+            },
+            controls,
+        );
+
+        Self {
+            controls: WidgetPod::new(Box::new(themed_controls)),
+        }
+    }
+}
+
+impl Widget<AppState> for HdrControlsWidget {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut AppState, env: &Env) {
+        self.controls.event(ctx, event, data, env);
+    }
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &AppState, env: &Env) {
+        self.controls.lifecycle(ctx, event, data, env);
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, _old_data: &AppState, data: &AppState, env: &Env) {
+        self.controls.update(ctx, data, env);
+    }
+
+    fn layout(
+        &mut self,
+        layout_ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &AppState,
+        env: &Env,
+    ) -> Size {
+        let size = self.controls.layout(layout_ctx, &bc.loosen(), data, env);
+        self.controls
+            .set_origin(layout_ctx, data, env, Point::new(0.0, 0.0));
+        size
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &AppState, env: &Env) {
+        let fill_color = if data.dark_theme_enabled {
+            Color::rgba(0.2, 0.2, 0.2, 0.5)
+        } else {
+            Color::rgba(1., 1., 1., 0.5)
+        };
+        let panel_rect = ctx.size().to_rect();
+        ctx.fill(RoundedRect::from_rect(panel_rect, 10.), &fill_color);
+
+        self.controls.paint(ctx, data, env);
+    }
+}
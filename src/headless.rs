@@ -0,0 +1,182 @@
+//! Scriptable subcommands, which decode images exactly as the viewer does
+
+use std::error::Error;
+use std::path::Path;
+
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, ImageFormat};
+use serde_json::{json, Map, Value};
+
+use crate::cli::HeadlessCommand;
+use crate::exif_metadata::{apply_orientation, describe_exif, get_orientation, read_exif};
+use crate::image_loader::{decode_image_file, get_format_name, read_image_data, DecodedImage};
+use crate::types::Direction;
+
+/// Runs a subcommand and returns the process exit code
+pub fn run(command: HeadlessCommand) -> i32 {
+    #[cfg(windows)]
+    attach_parent_console();
+
+    let result = match command {
+        HeadlessCommand::Info { file, json } => print_info(&file, json),
+        HeadlessCommand::Convert {
+            input,
+            output,
+            auto_orient,
+            rotate,
+            resize,
+        } => convert(&input, &output, auto_orient, rotate, resize),
+        HeadlessCommand::Thumb {
+            input,
+            output,
+            size,
+        } => write_thumbnail(&input, &output, size),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            1
+        }
+    }
+}
+
+fn load(path: &Path) -> Result<(DecodedImage, Vec<u8>), Box<dyn Error>> {
+    let data = read_image_data(path)?;
+    let decoded_image = decode_image_file(path, &data)?;
+    Ok((decoded_image, data))
+}
+
+/// The page the viewer would show first
+fn take_shown_page(decoded_image: DecodedImage) -> Result<DynamicImage, Box<dyn Error>> {
+    let page_index = decoded_image.page_index;
+    let page = decoded_image
+        .pages
+        .into_iter()
+        .nth(page_index)
+        .ok_or("The file contains no image")?;
+    Ok(page.image)
+}
+
+fn print_info(path: &Path, as_json: bool) -> Result<(), Box<dyn Error>> {
+    let (decoded_image, data) = load(path)?;
+    let image = &decoded_image.pages[decoded_image.page_index].image;
+    let (width, height) = image.dimensions();
+    let format = match &decoded_image.texture_info {
+        Some(texture_info) => texture_info.format_name.clone(),
+        None => get_format_name(path, &data),
+    };
+    let color_type = format!("{:?}", image.color());
+    let exif_fields = read_exif(&data)
+        .map(|exif| describe_exif(&exif))
+        .unwrap_or_default();
+
+    if as_json {
+        let exif: Map<String, Value> = exif_fields
+            .into_iter()
+            .map(|(tag, value)| (tag, Value::String(value)))
+            .collect();
+        let info = json!({
+            "path": path.to_string_lossy(),
+            "format": format,
+            "width": width,
+            "height": height,
+            "color_type": color_type,
+            "pages": decoded_image.pages.len(),
+            "icc_profile": decoded_image.icc_profile.is_some(),
+            "raw_preview": decoded_image.raw_preview,
+            "exif": exif,
+        });
+        println!("{}", serde_json::to_string_pretty(&info)?);
+    } else {
+        println!("Path: {}", path.display());
+        println!("Format: {}", format);
+        if decoded_image.raw_preview {
+            println!("Dimensions: {} × {} (embedded preview)", width, height);
+        } else {
+            println!("Dimensions: {} × {}", width, height);
+        }
+        println!("Color type: {}", color_type);
+        println!("Pages: {}", decoded_image.pages.len());
+        println!(
+            "ICC profile: {}",
+            if decoded_image.icc_profile.is_some() {
+                "embedded"
+            } else {
+                "none"
+            }
+        );
+        if !exif_fields.is_empty() {
+            println!("EXIF:");
+            for (tag, value) in exif_fields {
+                println!("  {}: {}", tag, value);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn convert(
+    input: &Path,
+    output: &Path,
+    auto_orient: bool,
+    rotate: Option<u32>,
+    resize: Option<(u32, u32)>,
+) -> Result<(), Box<dyn Error>> {
+    let (decoded_image, data) = load(input)?;
+    let mut image = take_shown_page(decoded_image)?;
+    if auto_orient {
+        if let Some(exif) = read_exif(&data) {
+            image = apply_orientation(image, get_orientation(&exif));
+        }
+    }
+    // The same quarter turns as the rotate buttons
+    for _ in 0..rotate.unwrap_or(0) / 90 {
+        image = Direction::Right.rotate(image);
+    }
+    if let Some((width, height)) = resize {
+        image = image.resize(width, height, FilterType::Lanczos3);
+    }
+    save(image, output)
+}
+
+fn write_thumbnail(input: &Path, output: &Path, size: u32) -> Result<(), Box<dyn Error>> {
+    let (decoded_image, data) = load(input)?;
+    let mut image = take_shown_page(decoded_image)?;
+    if let Some(exif) = read_exif(&data) {
+        image = apply_orientation(image, get_orientation(&exif));
+    }
+    save(image.thumbnail(size, size), output)
+}
+
+/// Saves in the format given by the extension, converting pixel data the format can't store
+fn save(image: DynamicImage, output: &Path) -> Result<(), Box<dyn Error>> {
+    let format = ImageFormat::from_path(output)?;
+    let is_floating_point = matches!(
+        image,
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+    );
+    let image = match format {
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8()),
+        ImageFormat::OpenExr => DynamicImage::ImageRgba32F(image.to_rgba32f()),
+        _ if is_floating_point => DynamicImage::ImageRgba8(image.to_rgba8()),
+        _ => image,
+    };
+    image.save_with_format(output, format)?;
+    Ok(())
+}
+
+/// The binary is built for the windows subsystem, so output only shows up in the console it was
+/// started from after attaching to it
+#[cfg(windows)]
+fn attach_parent_console() {
+    use winapi::shared::minwindef::{BOOL, DWORD};
+
+    const ATTACH_PARENT_PROCESS: DWORD = DWORD::MAX;
+    extern "system" {
+        fn AttachConsole(dw_process_id: DWORD) -> BOOL;
+    }
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
@@ -0,0 +1,91 @@
+use image::DynamicImage;
+
+pub const HISTOGRAM_BINS: usize = 256;
+
+pub struct Histogram {
+    pub red: [u32; HISTOGRAM_BINS],
+    pub green: [u32; HISTOGRAM_BINS],
+    pub blue: [u32; HISTOGRAM_BINS],
+    pub luminance: [u32; HISTOGRAM_BINS],
+    pub pixel_count: u64,
+    pub clipped_shadows: u64,
+    pub clipped_highlights: u64,
+}
+
+impl Histogram {
+    /// Counts 8-bit channel values for the whole image. A pixel is considered clipped only if all
+    /// of its color channels sit at the same end of the range, so saturated colors aren't.
+    pub fn from_image(image: &DynamicImage) -> Self {
+        let mut histogram = Self {
+            red: [0; HISTOGRAM_BINS],
+            green: [0; HISTOGRAM_BINS],
+            blue: [0; HISTOGRAM_BINS],
+            luminance: [0; HISTOGRAM_BINS],
+            pixel_count: 0,
+            clipped_shadows: 0,
+            clipped_highlights: 0,
+        };
+
+        let rgb = image.to_rgb8();
+        for pixel in rgb.pixels() {
+            let [r, g, b] = pixel.0;
+            histogram.red[r as usize] += 1;
+            histogram.green[g as usize] += 1;
+            histogram.blue[b as usize] += 1;
+
+            // Rec. 709 coefficients, matching image's own luma conversion
+            let luma = (2126 * r as u32 + 7152 * g as u32 + 722 * b as u32) / 10000;
+            histogram.luminance[luma as usize] += 1;
+
+            if r == 0 && g == 0 && b == 0 {
+                histogram.clipped_shadows += 1;
+            }
+            if r == 255 && g == 255 && b == 255 {
+                histogram.clipped_highlights += 1;
+            }
+            histogram.pixel_count += 1;
+        }
+
+        histogram
+    }
+
+    /// The tallest bin across all channels, ignoring the two end bins so that clipped pixels
+    /// don't flatten the rest of the graph.
+    pub fn get_peak(&self) -> u32 {
+        [&self.red, &self.green, &self.blue, &self.luminance]
+            .iter()
+            .flat_map(|channel| channel[1..HISTOGRAM_BINS - 1].iter())
+            .copied()
+            .max()
+            .unwrap_or(0)
+            .max(1)
+    }
+
+    pub fn get_clipped_shadows_percent(&self) -> f64 {
+        self.clipped_shadows as f64 / self.pixel_count.max(1) as f64 * 100.
+    }
+
+    pub fn get_clipped_highlights_percent(&self) -> f64 {
+        self.clipped_highlights as f64 / self.pixel_count.max(1) as f64 * 100.
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn saturated_colors_are_not_clipped() {
+        let mut image = RgbImage::from_pixel(4, 1, Rgb([255, 0, 0]));
+        image.put_pixel(1, 0, Rgb([0, 0, 0]));
+        image.put_pixel(2, 0, Rgb([255, 255, 255]));
+        image.put_pixel(3, 0, Rgb([0, 255, 255]));
+        let histogram = Histogram::from_image(&DynamicImage::ImageRgb8(image));
+        assert_eq!(histogram.pixel_count, 4);
+        assert_eq!(histogram.clipped_shadows, 1);
+        assert_eq!(histogram.clipped_highlights, 1);
+        assert_eq!(histogram.red[255], 2);
+        assert_eq!(histogram.get_clipped_highlights_percent(), 25.);
+    }
+}
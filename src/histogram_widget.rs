@@ -0,0 +1,145 @@
+use druid::kurbo::{BezPath, RoundedRect};
+use druid::piet::{Text, TextLayout, TextLayoutBuilder};
+use druid::widget::prelude::*;
+use druid::{Color, FontFamily, Point, Rect};
+
+use crate::app_state::AppState;
+use crate::histogram::{Histogram, HISTOGRAM_BINS};
+use crate::image_container::ImageState;
+
+pub struct HistogramWidget {
+    size: Size,
+}
+
+impl HistogramWidget {
+    pub fn new(size: Size) -> Self {
+        Self { size }
+    }
+
+    fn channel_path(channel: &[u32; HISTOGRAM_BINS], peak: u32, graph_rect: Rect) -> BezPath {
+        let bin_width = graph_rect.width() / (HISTOGRAM_BINS - 1) as f64;
+        let mut path = BezPath::new();
+        path.move_to(Point::new(graph_rect.x0, graph_rect.y1));
+        for (bin, count) in channel.iter().enumerate() {
+            let height = (*count as f64 / peak as f64).min(1.) * graph_rect.height();
+            path.line_to(Point::new(
+                graph_rect.x0 + bin as f64 * bin_width,
+                graph_rect.y1 - height,
+            ));
+        }
+        path.line_to(Point::new(graph_rect.x1, graph_rect.y1));
+        path.close_path();
+        path
+    }
+
+    fn paint_histogram(
+        &self,
+        ctx: &mut PaintCtx,
+        histogram: &Histogram,
+        graph_rect: Rect,
+        dark_theme_enabled: bool,
+    ) {
+        let peak = histogram.get_peak();
+        let channels = [
+            (&histogram.red, Color::rgba8(191, 97, 106, 140)),
+            (&histogram.green, Color::rgba8(163, 190, 140, 140)),
+            (&histogram.blue, Color::rgba8(94, 129, 172, 140)),
+        ];
+        for (channel, color) in channels {
+            let path = Self::channel_path(channel, peak, graph_rect);
+            ctx.fill(path, &color);
+        }
+
+        let luminance_color = if dark_theme_enabled {
+            Color::rgba8(255, 255, 255, 200)
+        } else {
+            Color::rgba8(0, 0, 0, 200)
+        };
+        let luminance_path = Self::channel_path(&histogram.luminance, peak, graph_rect);
+        ctx.stroke(luminance_path, &luminance_color, 1.);
+    }
+}
+
+impl Widget<AppState> for HistogramWidget {
+    fn event(&mut self, _ctx: &mut EventCtx, _event: &Event, _data: &mut AppState, _env: &Env) {}
+
+    fn lifecycle(
+        &mut self,
+        _ctx: &mut LifeCycleCtx,
+        _event: &LifeCycle,
+        _data: &AppState,
+        _env: &Env,
+    ) {
+    }
+
+    fn update(&mut self, _ctx: &mut UpdateCtx, _old_data: &AppState, _data: &AppState, _env: &Env) {
+    }
+
+    fn layout(
+        &mut self,
+        _layout_ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        _data: &AppState,
+        _env: &Env,
+    ) -> Size {
+        bc.constrain(self.size)
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &AppState, _env: &Env) {
+        let histogram = {
+            let image_state_guard = data.get_image_ref();
+            let image_state = image_state_guard.lock().unwrap();
+            if let ImageState::Loaded(image_container) = &*image_state {
+                image_container.get_histogram()
+            } else {
+                return;
+            }
+        };
+
+        let (text_color, fill_color) = if data.dark_theme_enabled {
+            (Color::rgb8(255, 255, 255), Color::rgba(0.2, 0.2, 0.2, 0.5))
+        } else {
+            (Color::rgb8(0, 0, 0), Color::rgba(1., 1., 1., 0.5))
+        };
+
+        let widget_rect = ctx.size().to_rect();
+        let widget_rect_rounded = RoundedRect::from_rect(widget_rect, 10.);
+        let graph_rect = Rect::new(
+            widget_rect.x0 + 10.,
+            widget_rect.y0 + 10.,
+            widget_rect.x1 - 10.,
+            widget_rect.y1 - 30.,
+        );
+
+        let caption = if let Some(histogram) = &histogram {
+            format!(
+                "Shadows clipped: {:.2}%    Highlights clipped: {:.2}%",
+                histogram.get_clipped_shadows_percent(),
+                histogram.get_clipped_highlights_percent()
+            )
+        } else {
+            "Computing histogram...".to_string()
+        };
+        let layout = ctx
+            .text()
+            .new_text_layout(caption)
+            .font(FontFamily::SYSTEM_UI, 11.)
+            .text_color(text_color)
+            .build()
+            .unwrap();
+        let text_bounds = layout.image_bounds();
+
+        ctx.with_save(|ctx| {
+            ctx.clip(widget_rect_rounded);
+            ctx.fill(widget_rect, &fill_color);
+            if let Some(histogram) = &histogram {
+                self.paint_histogram(ctx, histogram, graph_rect, data.dark_theme_enabled);
+            }
+            let text_point = Point::new(
+                widget_rect.center().x - text_bounds.center().x,
+                widget_rect.y1 - 15. - text_bounds.center().y,
+            );
+            ctx.draw_text(&layout, text_point);
+        });
+    }
+}
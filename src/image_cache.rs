@@ -0,0 +1,111 @@
+//! Decoded images kept in memory, so stepping to a prefetched or recently shown image is instant
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::archive::split_archive_path;
+use crate::image_loader::DecodedImage;
+
+struct CacheEntry {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    image: DecodedImage,
+    size: usize,
+}
+
+/// Least recently used images are dropped first once the memory limit is reached
+pub struct ImageCache {
+    entries: VecDeque<CacheEntry>,
+    memory_limit: usize,
+    memory_used: usize,
+}
+
+impl ImageCache {
+    pub fn new(memory_limit: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            memory_limit,
+            memory_used: 0,
+        }
+    }
+
+    /// Returns a copy of the cached image, unless the file changed since it was decoded
+    pub fn get(&mut self, path: &Path) -> Option<DecodedImage> {
+        let index = self.entries.iter().position(|entry| entry.path == path)?;
+        let entry = self.entries.remove(index)?;
+        if entry.modified != get_modified_time(path) {
+            self.memory_used -= entry.size;
+            return None;
+        }
+        let image = entry.image.clone();
+        self.entries.push_back(entry);
+        Some(image)
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.entries.iter().any(|entry| entry.path == path)
+    }
+
+    pub fn insert(&mut self, path: &Path, image: DecodedImage) {
+        self.remove(path);
+        let size = get_memory_size(&image);
+        if size > self.memory_limit {
+            return;
+        }
+        self.entries.push_back(CacheEntry {
+            path: path.to_path_buf(),
+            modified: get_modified_time(path),
+            image,
+            size,
+        });
+        self.memory_used += size;
+        self.evict();
+    }
+
+    pub fn set_memory_limit(&mut self, memory_limit: usize) {
+        self.memory_limit = memory_limit;
+        self.evict();
+    }
+
+    fn remove(&mut self, path: &Path) {
+        if let Some(index) = self.entries.iter().position(|entry| entry.path == path) {
+            if let Some(entry) = self.entries.remove(index) {
+                self.memory_used -= entry.size;
+            }
+        }
+    }
+
+    fn evict(&mut self) {
+        while self.memory_used > self.memory_limit {
+            match self.entries.pop_front() {
+                Some(entry) => self.memory_used -= entry.size,
+                None => break,
+            }
+        }
+    }
+}
+
+fn get_memory_size(image: &DecodedImage) -> usize {
+    image
+        .pages
+        .iter()
+        .map(|page| {
+            page.image.as_bytes().len()
+                + page
+                    .mip_levels
+                    .iter()
+                    .map(|level| level.as_bytes().len())
+                    .sum::<usize>()
+        })
+        .sum()
+}
+
+/// Archive entries change along with the archive itself
+fn get_modified_time(path: &Path) -> Option<SystemTime> {
+    let file_path = match split_archive_path(path) {
+        Some((archive_path, _entry_name)) => archive_path,
+        None => path.to_path_buf(),
+    };
+    std::fs::metadata(file_path).ok()?.modified().ok()
+}
@@ -1,10 +1,16 @@
+use std::sync::Arc;
 use std::time::Instant;
 
 use druid::piet::PietImage;
 use druid::widget::prelude::*;
-use image::DynamicImage;
+use image::{DynamicImage, Rgba, RgbaImage};
 
+use crate::color_management::ColorManagement;
+use crate::dds;
 use crate::events::*;
+use crate::histogram::Histogram;
+use crate::image_loader::{DecodedImage, ImagePage, TextureInfo};
+use crate::types::{ChannelView, RenderSettings};
 
 #[derive(Clone, Data)]
 pub enum ImageState {
@@ -22,28 +28,166 @@ impl FailedImageContainer {
     }
 }
 
+/// The luminance extremes of a floating point image, ignoring fully black pixels
+#[derive(Clone, Copy)]
+pub struct DynamicRange {
+    pub min_luminance: f32,
+    pub max_luminance: f32,
+}
+
+impl DynamicRange {
+    fn measure(image: &DynamicImage) -> Option<Self> {
+        let hdr_image = match image {
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => image.to_rgb32f(),
+            _ => return None,
+        };
+
+        let mut min_luminance = f32::MAX;
+        let mut max_luminance: f32 = 0.;
+        for pixel in hdr_image.pixels() {
+            let [r, g, b] = pixel.0;
+            let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+            if luminance > 0. && luminance.is_finite() {
+                min_luminance = min_luminance.min(luminance);
+                max_luminance = max_luminance.max(luminance);
+            }
+        }
+
+        if max_luminance > 0. {
+            Some(Self {
+                min_luminance,
+                max_luminance,
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn get_stops(&self) -> f32 {
+        (self.max_luminance / self.min_luminance).log2()
+    }
+}
+
 #[derive(Clone, Data)]
 pub struct ImageContainer {
     load_request_timestamp: Instant,
     #[data(ignore)]
-    image_data: DynamicImage,
+    pages: Vec<ImagePage>,
+    page_index: usize,
+    mip_level: usize,
+    #[data(ignore)]
+    texture_info: Option<TextureInfo>,
+    cube_cross_layout: bool,
+    #[data(ignore)]
+    cube_cross_image: Option<DynamicImage>,
+    #[data(ignore)]
+    icc_profile: Option<Arc<Vec<u8>>>,
+    #[data(ignore)]
+    vector_source: Option<Arc<Vec<u8>>>,
+    raw_preview: bool,
     image_size: Size,
     #[data(ignore)]
     image_cache: Option<PietImage>,
     #[data(ignore)]
+    image_cache_settings: Option<RenderSettings>,
+    #[data(ignore)]
+    dynamic_range: Option<DynamicRange>,
+    #[data(ignore)]
+    histogram: Option<Arc<Histogram>>,
+    #[data(ignore)]
     pub event_queue: Option<MouseEvent>,
 }
 
 impl ImageContainer {
-    pub fn new(image_data: DynamicImage, load_request_timestamp: Instant) -> Self {
-        let image_size = Size::new(image_data.width() as f64, image_data.height() as f64);
-        Self {
+    pub fn new(decoded_image: DecodedImage, load_request_timestamp: Instant) -> Self {
+        let mut container = Self {
             load_request_timestamp,
-            image_data,
-            image_size,
+            pages: decoded_image.pages,
+            page_index: 0,
+            mip_level: 0,
+            texture_info: decoded_image.texture_info,
+            cube_cross_layout: false,
+            cube_cross_image: None,
+            icc_profile: decoded_image.icc_profile.map(Arc::new),
+            vector_source: decoded_image.vector_source,
+            raw_preview: decoded_image.raw_preview,
+            image_size: Size::ZERO,
             image_cache: None,
+            image_cache_settings: None,
+            dynamic_range: None,
+            histogram: None,
             event_queue: None,
-        }
+        };
+        container.set_page(decoded_image.page_index);
+        container
+    }
+    pub fn get_page_count(&self) -> usize {
+        self.pages.len()
+    }
+    pub fn get_page_index(&self) -> usize {
+        self.page_index
+    }
+    pub fn get_page_label(&self) -> Option<&String> {
+        self.pages[self.page_index].label.as_ref()
+    }
+    pub fn get_pages(&self) -> &Vec<ImagePage> {
+        &self.pages
+    }
+    pub fn get_texture_info(&self) -> Option<&TextureInfo> {
+        self.texture_info.as_ref()
+    }
+    pub fn get_icc_profile(&self) -> Option<&Vec<u8>> {
+        self.icc_profile.as_deref()
+    }
+    pub fn get_vector_source(&self) -> Option<&Arc<Vec<u8>>> {
+        self.vector_source.as_ref()
+    }
+    pub fn is_raw_preview(&self) -> bool {
+        self.raw_preview
+    }
+    pub fn get_mip_level_count(&self) -> usize {
+        self.pages[self.page_index].mip_levels.len() + 1
+    }
+    pub fn get_mip_level(&self) -> usize {
+        self.mip_level
+    }
+    pub fn set_mip_level(&mut self, mip_level: usize) {
+        self.mip_level = mip_level;
+        self.set_page(self.page_index);
+    }
+    pub fn is_cube_map(&self) -> bool {
+        matches!(&self.texture_info, Some(info) if info.face_count == 6)
+    }
+    pub fn get_cube_cross_layout(&self) -> bool {
+        self.cube_cross_layout
+    }
+    pub fn set_cube_cross_layout(&mut self, enabled: bool) {
+        self.cube_cross_layout = enabled && self.is_cube_map();
+        self.set_page(self.page_index);
+    }
+    /// Switches to another sub-image of the file, dropping everything derived from the old one
+    pub fn set_page(&mut self, page_index: usize) {
+        self.page_index = page_index.min(self.pages.len() - 1);
+        self.mip_level = self.mip_level.min(self.get_mip_level_count() - 1);
+        self.cube_cross_image = if self.cube_cross_layout {
+            let first_face = self.page_index / 6 * 6;
+            let faces: Vec<&DynamicImage> = self.pages[first_face..first_face + 6]
+                .iter()
+                .map(|page| self.get_page_level(page))
+                .collect();
+            Some(dds::compose_cube_cross(&faces))
+        } else {
+            None
+        };
+        let image_data = self.get_image();
+        let image_size = Size::new(image_data.width() as f64, image_data.height() as f64);
+        let dynamic_range = DynamicRange::measure(image_data);
+        self.image_size = image_size;
+        self.dynamic_range = dynamic_range;
+        self.image_cache = None;
+        self.image_cache_settings = None;
+        self.histogram = None;
+        self.event_queue = None;
     }
     pub fn get_timestamp(&self) -> &Instant {
         &self.load_request_timestamp
@@ -52,15 +196,109 @@ impl ImageContainer {
         self.image_size
     }
     pub fn get_image(&self) -> &DynamicImage {
-        &self.image_data
+        match &self.cube_cross_image {
+            Some(cube_cross_image) => cube_cross_image,
+            None => self.get_page_level(&self.pages[self.page_index]),
+        }
+    }
+    fn get_page_level<'a>(&self, page: &'a ImagePage) -> &'a DynamicImage {
+        match self.mip_level {
+            0 => &page.image,
+            level => &page.mip_levels[level - 1],
+        }
+    }
+    pub fn has_alpha(&self) -> bool {
+        self.get_image().color().has_alpha()
     }
-    pub fn has_cache(&self) -> bool {
-        matches!(self.image_cache, Some(_))
+    pub fn is_hdr(&self) -> bool {
+        matches!(
+            self.get_image(),
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+        )
     }
-    pub fn set_cache(&mut self, cached_image: PietImage) {
+    pub fn get_dynamic_range(&self) -> Option<DynamicRange> {
+        self.dynamic_range
+    }
+    pub fn has_cache_for(&self, settings: &RenderSettings) -> bool {
+        match &self.image_cache_settings {
+            // Exposure and tone mapping only change the output of floating point images
+            Some(cached) if self.is_hdr() => cached == settings,
+            Some(cached) => {
+                cached.channel_view == settings.channel_view
+                    && cached.color_revision == settings.color_revision
+            }
+            None => false,
+        }
+    }
+    pub fn set_cache(&mut self, cached_image: PietImage, settings: RenderSettings) {
         self.image_cache = Some(cached_image);
+        self.image_cache_settings = Some(settings);
     }
     pub fn get_cache(&self) -> Option<&PietImage> {
         self.image_cache.as_ref()
     }
+    pub fn has_histogram(&self) -> bool {
+        self.histogram.is_some()
+    }
+    pub fn set_histogram(&mut self, histogram: Arc<Histogram>) {
+        self.histogram = Some(histogram);
+    }
+    pub fn get_histogram(&self) -> Option<Arc<Histogram>> {
+        self.histogram.clone()
+    }
+    /// Builds the RGBA buffer that gets uploaded for display. Floating point images are exposed
+    /// and tone mapped first. The composite view is then converted to the monitor profile, while
+    /// the other views collapse every channel but the selected one into a grayscale rendition.
+    pub fn get_display_rgba(
+        &self,
+        settings: &RenderSettings,
+        color_management: &ColorManagement,
+    ) -> RgbaImage {
+        let mut rgba = if self.is_hdr() {
+            self.tone_map(settings)
+        } else {
+            self.get_image().to_rgba8()
+        };
+
+        let channel_view = settings.channel_view;
+        if let ChannelView::Composite = channel_view {
+            color_management
+                .convert_for_display(&mut rgba, self.icc_profile.as_ref().map(|p| p.as_slice()));
+            return rgba;
+        }
+        for pixel in rgba.pixels_mut() {
+            let [r, g, b, a] = pixel.0;
+            let value = match channel_view {
+                ChannelView::Red => r,
+                ChannelView::Green => g,
+                ChannelView::Blue => b,
+                ChannelView::Alpha => a,
+                // Rec. 709 coefficients, matching image's own luma conversion
+                _ => ((2126 * r as u32 + 7152 * g as u32 + 722 * b as u32) / 10000) as u8,
+            };
+            *pixel = Rgba([value, value, value, 255]);
+        }
+        rgba
+    }
+
+    fn tone_map(&self, settings: &RenderSettings) -> RgbaImage {
+        let hdr_image = self.get_image().to_rgba32f();
+        let exposure_scale = 2_f32.powf(settings.exposure as f32);
+        let inverse_gamma = 1. / settings.gamma.max(0.1) as f32;
+        let tone_mapping = settings.tone_mapping;
+        let encode = |value: f32| {
+            let mapped = tone_mapping.apply(value * exposure_scale);
+            (mapped.powf(inverse_gamma) * 255.).round() as u8
+        };
+
+        RgbaImage::from_fn(hdr_image.width(), hdr_image.height(), |x, y| {
+            let [r, g, b, a] = hdr_image.get_pixel(x, y).0;
+            Rgba([
+                encode(r),
+                encode(g),
+                encode(b),
+                (a.clamp(0., 1.) * 255.).round() as u8,
+            ])
+        })
+    }
 }
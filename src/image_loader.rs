@@ -0,0 +1,736 @@
+use std::error::Error;
+use std::ffi::OsStr;
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::Arc;
+
+use image::{
+    DynamicImage, GrayAlphaImage, GrayImage, ImageBuffer, ImageFormat, Rgb, Rgb32FImage, RgbImage,
+    Rgba, Rgba32FImage, RgbaImage,
+};
+
+use crate::archive;
+use crate::dds;
+use crate::extended_formats;
+use crate::raw_image;
+use crate::vector_image;
+
+/// Lowercase extensions of every format the loader can open
+const SUPPORTED_EXTENSIONS: &[&str] = &[
+    "png",
+    "jpg",
+    "jpeg",
+    "bmp",
+    "ico",
+    "tiff",
+    "webp",
+    #[cfg(any(feature = "avif", feature = "heif"))]
+    "avif",
+    "pnm",
+    "dds",
+    "tga",
+    "exr",
+    "svg",
+    "svgz",
+    #[cfg(feature = "qoi")]
+    "qoi",
+    #[cfg(feature = "jxl")]
+    "jxl",
+    #[cfg(feature = "heif")]
+    "heic",
+    #[cfg(feature = "heif")]
+    "heif",
+];
+
+/// The same list for the open dialog, in both cases since some platforms match them exactly
+pub const FILE_DIALOG_EXTENSIONS: &[&str] = &[
+    "png",
+    "PNG",
+    "jpg",
+    "jpeg",
+    "JPG",
+    "JPEG",
+    "bmp",
+    "BMP",
+    "ico",
+    "ICO",
+    "tiff",
+    "TIFF",
+    "webp",
+    "WEBP",
+    #[cfg(any(feature = "avif", feature = "heif"))]
+    "avif",
+    #[cfg(any(feature = "avif", feature = "heif"))]
+    "AVIF",
+    "pnm",
+    "PNM",
+    "dds",
+    "DDS",
+    "tga",
+    "TGA",
+    "exr",
+    "EXR",
+    "svg",
+    "SVG",
+    "svgz",
+    "SVGZ",
+    #[cfg(feature = "qoi")]
+    "qoi",
+    #[cfg(feature = "qoi")]
+    "QOI",
+    #[cfg(feature = "jxl")]
+    "jxl",
+    #[cfg(feature = "jxl")]
+    "JXL",
+    #[cfg(feature = "heif")]
+    "heic",
+    #[cfg(feature = "heif")]
+    "HEIC",
+    #[cfg(feature = "heif")]
+    "heif",
+    #[cfg(feature = "heif")]
+    "HEIF",
+];
+
+pub fn is_supported_extension(extension: &OsStr) -> bool {
+    let extension = extension.to_ascii_lowercase();
+    SUPPORTED_EXTENSIONS
+        .iter()
+        .chain(raw_image::RAW_EXTENSIONS.iter())
+        .any(|supported_extension| extension == *supported_extension)
+}
+
+/// A single displayable image inside a file, such as a TIFF page, an EXR part or an icon size
+#[derive(Clone)]
+pub struct ImagePage {
+    pub image: DynamicImage,
+    pub label: Option<String>,
+    /// Progressively smaller versions of the image, for textures that carry a mip chain
+    pub mip_levels: Vec<DynamicImage>,
+}
+
+impl ImagePage {
+    pub fn new(image: DynamicImage, label: Option<String>) -> Self {
+        Self {
+            image,
+            label,
+            mip_levels: Vec::new(),
+        }
+    }
+}
+
+/// GPU texture details that are shown alongside the page information
+#[derive(Clone)]
+pub struct TextureInfo {
+    pub format_name: String,
+    /// Six for cube maps, where pages are grouped face by face within each array slice
+    pub face_count: usize,
+}
+
+/// Everything decoded from one file, along with the page that should be shown first
+#[derive(Clone)]
+pub struct DecodedImage {
+    pub pages: Vec<ImagePage>,
+    pub page_index: usize,
+    pub texture_info: Option<TextureInfo>,
+    /// The embedded ICC profile, if the file carries one
+    pub icc_profile: Option<Vec<u8>>,
+    /// The source document for vector formats, which get re-rendered as the zoom level changes
+    pub vector_source: Option<Arc<Vec<u8>>>,
+    /// Set for the embedded preview of a camera RAW file, which can be developed in full later
+    pub raw_preview: bool,
+}
+
+impl DecodedImage {
+    pub fn from_image(image: DynamicImage) -> Self {
+        Self {
+            pages: vec![ImagePage::new(image, None)],
+            page_index: 0,
+            texture_info: None,
+            icc_profile: None,
+            vector_source: None,
+            raw_preview: false,
+        }
+    }
+
+    pub fn from_pages(pages: Vec<ImagePage>) -> Option<Self> {
+        if pages.is_empty() {
+            None
+        } else {
+            Some(Self {
+                pages,
+                page_index: 0,
+                texture_info: None,
+                icc_profile: None,
+                vector_source: None,
+                raw_preview: false,
+            })
+        }
+    }
+}
+
+pub fn load_image_file(path: &Path) -> Result<DecodedImage, Box<dyn Error>> {
+    let data = read_image_data(path)?;
+    decode_image_file(path, &data)
+}
+
+/// Reads a file, or an entry if the path points inside an archive
+pub fn read_image_data(path: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+    match archive::split_archive_path(path) {
+        Some((archive_path, entry_name)) => archive::read_archive_entry(&archive_path, &entry_name),
+        None => Ok(std::fs::read(path)?),
+    }
+}
+
+/// Decodes file contents, using the path to pick the decoder where the contents alone can't
+pub fn decode_image_file(path: &Path, data: &[u8]) -> Result<DecodedImage, Box<dyn Error>> {
+    // SVG isn't an ImageFormat, so it has to be recognized by its extension
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_ascii_lowercase());
+    if extension.map_or(false, |extension| extension == "svg" || extension == "svgz") {
+        return vector_image::decode_svg(data);
+    }
+    // Most RAW formats are TIFF based, so this has to come before format detection
+    if raw_image::is_raw_path(path) {
+        return raw_image::decode_raw_preview(data);
+    }
+    if let Some(result) = extended_formats::decode_extended_format(path, data) {
+        return result;
+    }
+    // Formats like TGA have no magic number, so the extension takes precedence
+    let format = match ImageFormat::from_path(path) {
+        Ok(format) => format,
+        Err(_) => image::guess_format(data)?,
+    };
+    // The image crate only reads the top mip level of a few DDS formats, so textures get their
+    // own decoder
+    if format == ImageFormat::Dds {
+        if let Ok(decoded_image) = dds::decode_dds(data) {
+            return Ok(decoded_image);
+        }
+    }
+    // AVIF decoding in the image crate is optional, and libheif can often stand in for it
+    #[cfg(feature = "heif")]
+    if format == ImageFormat::Avif {
+        if let Ok(decoded_image) = extended_formats::decode_heif(data) {
+            return Ok(decoded_image);
+        }
+    }
+    let mut decoded_image = decode_image_data(data, format)?;
+    decoded_image.icc_profile = extract_icc_profile(data, format);
+    Ok(decoded_image)
+}
+
+/// A short name for the format of a file, following the same detection order as decoding
+pub fn get_format_name(path: &Path, data: &[u8]) -> String {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_uppercase())
+        .unwrap_or_default();
+    if raw_image::is_raw_path(path) {
+        return format!("Camera RAW ({})", extension);
+    }
+    if extension == "SVG" || extension == "SVGZ" {
+        return "SVG".to_string();
+    }
+    match ImageFormat::from_path(path).or_else(|_| image::guess_format(data)) {
+        Ok(format) => format!("{:?}", format).to_ascii_uppercase(),
+        Err(_) => extension,
+    }
+}
+
+fn decode_image_data(data: &[u8], format: ImageFormat) -> Result<DecodedImage, Box<dyn Error>> {
+    // Containers that can hold several images get enumerated; if that fails for any reason, fall
+    // back to whatever single image the image crate picks by default
+    let sub_images = match format {
+        ImageFormat::Tiff => decode_tiff_pages(data).ok(),
+        ImageFormat::OpenExr => decode_exr_layers(data).ok(),
+        ImageFormat::Ico => decode_ico_entries(data).ok(),
+        _ => None,
+    };
+    if let Some(decoded_image) = sub_images.and_then(DecodedImage::from_pages) {
+        return Ok(decoded_image);
+    }
+
+    let image = image::load_from_memory_with_format(data, format)?;
+    Ok(DecodedImage::from_image(image))
+}
+
+fn decode_tiff_pages(data: &[u8]) -> Result<Vec<ImagePage>, Box<dyn Error>> {
+    use tiff::decoder::{Decoder, DecodingResult};
+    use tiff::ColorType;
+
+    let mut decoder = Decoder::new(Cursor::new(data))?;
+    let mut pages = Vec::new();
+    loop {
+        let (width, height) = decoder.dimensions()?;
+        let color_type = decoder.colortype()?;
+        let page_image = match (color_type, decoder.read_image()?) {
+            (ColorType::Gray(8), DecodingResult::U8(buffer)) => {
+                GrayImage::from_raw(width, height, buffer).map(DynamicImage::ImageLuma8)
+            }
+            (ColorType::GrayA(8), DecodingResult::U8(buffer)) => {
+                GrayAlphaImage::from_raw(width, height, buffer).map(DynamicImage::ImageLumaA8)
+            }
+            (ColorType::RGB(8), DecodingResult::U8(buffer)) => {
+                RgbImage::from_raw(width, height, buffer).map(DynamicImage::ImageRgb8)
+            }
+            (ColorType::RGBA(8), DecodingResult::U8(buffer)) => {
+                RgbaImage::from_raw(width, height, buffer).map(DynamicImage::ImageRgba8)
+            }
+            (ColorType::Gray(16), DecodingResult::U16(buffer)) => {
+                ImageBuffer::from_raw(width, height, buffer).map(DynamicImage::ImageLuma16)
+            }
+            (ColorType::RGB(16), DecodingResult::U16(buffer)) => {
+                ImageBuffer::<Rgb<u16>, _>::from_raw(width, height, buffer)
+                    .map(DynamicImage::ImageRgb16)
+            }
+            (ColorType::RGBA(16), DecodingResult::U16(buffer)) => {
+                ImageBuffer::<Rgba<u16>, _>::from_raw(width, height, buffer)
+                    .map(DynamicImage::ImageRgba16)
+            }
+            (ColorType::RGB(32), DecodingResult::F32(buffer)) => {
+                Rgb32FImage::from_raw(width, height, buffer).map(DynamicImage::ImageRgb32F)
+            }
+            (ColorType::RGBA(32), DecodingResult::F32(buffer)) => {
+                Rgba32FImage::from_raw(width, height, buffer).map(DynamicImage::ImageRgba32F)
+            }
+            _ => None,
+        };
+
+        // Pages with an unsupported layout are skipped rather than failing the whole file
+        if let Some(image) = page_image {
+            pages.push(ImagePage::new(image, None));
+        }
+
+        if !decoder.more_images() {
+            break;
+        }
+        decoder.next_image()?;
+    }
+
+    Ok(pages)
+}
+
+fn decode_exr_layers(data: &[u8]) -> Result<Vec<ImagePage>, Box<dyn Error>> {
+    use exr::prelude::*;
+
+    // Every channel is read, as asking for RGBA fails the whole file when any layer lacks it
+    let exr_image = read()
+        .no_deep_data()
+        .largest_resolution_level()
+        .all_channels()
+        .all_layers()
+        .all_attributes()
+        .from_buffered(Cursor::new(data))?;
+
+    let mut pages = Vec::new();
+    for layer in exr_image.layer_data {
+        let pixel_count = layer.size.area();
+        let channel_values = |name: &str| {
+            layer
+                .channel_data
+                .list
+                .iter()
+                .find(|channel| channel.name.eq_case_insensitive(name))
+                .map(|channel| channel.sample_data.values_as_f32().collect::<Vec<_>>())
+                .filter(|values| values.len() == pixel_count)
+        };
+
+        // Layers without color, like depth or masks, are skipped rather than failing the file
+        let (red, green, blue) = match (
+            channel_values("R"),
+            channel_values("G"),
+            channel_values("B"),
+        ) {
+            (Some(red), Some(green), Some(blue)) => (red, green, blue),
+            _ => continue,
+        };
+        let alpha = channel_values("A").unwrap_or_else(|| vec![1.; pixel_count]);
+        let mut buffer = Vec::with_capacity(pixel_count * 4);
+        for index in 0..pixel_count {
+            buffer.extend_from_slice(&[red[index], green[index], blue[index], alpha[index]]);
+        }
+
+        // The exr prelude has its own image module, so image crate types are fully qualified
+        let rgba = ::image::Rgba32FImage::from_raw(
+            layer.size.width() as u32,
+            layer.size.height() as u32,
+            buffer,
+        );
+        if let Some(rgba) = rgba {
+            pages.push(ImagePage::new(
+                ::image::DynamicImage::ImageRgba32F(rgba),
+                layer.attributes.layer_name.map(|name| name.to_string()),
+            ));
+        }
+    }
+
+    Ok(pages)
+}
+
+fn decode_ico_entries(data: &[u8]) -> Result<Vec<ImagePage>, Box<dyn Error>> {
+    const HEADER_SIZE: usize = 6;
+    const ENTRY_SIZE: usize = 16;
+    let read_u16 = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+    let read_u32 = |offset: usize| {
+        u32::from_le_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ])
+    };
+
+    if data.len() < HEADER_SIZE {
+        return Err("Truncated icon header".into());
+    }
+    let entry_count = read_u16(4) as usize;
+    if data.len() < HEADER_SIZE + entry_count * ENTRY_SIZE {
+        return Err("Truncated icon directory".into());
+    }
+
+    let mut pages = Vec::new();
+    for entry_index in 0..entry_count {
+        let entry_offset = HEADER_SIZE + entry_index * ENTRY_SIZE;
+        let entry = &data[entry_offset..entry_offset + ENTRY_SIZE];
+        let image_size = read_u32(entry_offset + 8) as usize;
+        let image_offset = read_u32(entry_offset + 12) as usize;
+        let image_data = match data.get(image_offset..image_offset + image_size) {
+            Some(image_data) => image_data,
+            None => continue,
+        };
+
+        // Repackage the entry as a single-image icon so the regular decoder can handle both the
+        // PNG and the BMP flavored entries
+        let mut single_icon = Vec::with_capacity(HEADER_SIZE + ENTRY_SIZE + image_size);
+        single_icon.extend_from_slice(&[0, 0, 1, 0, 1, 0]);
+        single_icon.extend_from_slice(&entry[..12]);
+        single_icon.extend_from_slice(&((HEADER_SIZE + ENTRY_SIZE) as u32).to_le_bytes());
+        single_icon.extend_from_slice(image_data);
+
+        if let Ok(image) = image::load_from_memory_with_format(&single_icon, ImageFormat::Ico) {
+            let label = format!("{} × {}", image.width(), image.height());
+            pages.push(ImagePage::new(image, Some(label)));
+        }
+    }
+
+    // Show the largest size first, as the default decoder would
+    pages.sort_by_key(|page| {
+        std::cmp::Reverse(page.image.width() as u64 * page.image.height() as u64)
+    });
+
+    Ok(pages)
+}
+
+/// Pulls the embedded ICC profile out of the formats that commonly carry one
+fn extract_icc_profile(data: &[u8], format: ImageFormat) -> Option<Vec<u8>> {
+    match format {
+        ImageFormat::Png => {
+            let reader = png::Decoder::new(Cursor::new(data)).read_info().ok()?;
+            reader
+                .info()
+                .icc_profile
+                .as_ref()
+                .map(|profile| profile.to_vec())
+        }
+        ImageFormat::Jpeg => extract_jpeg_icc_profile(data),
+        ImageFormat::Tiff => {
+            const ICC_PROFILE_TAG: u16 = 34675;
+            let mut decoder = tiff::decoder::Decoder::new(Cursor::new(data)).ok()?;
+            decoder
+                .get_tag_u8_vec(tiff::tags::Tag::Unknown(ICC_PROFILE_TAG))
+                .ok()
+        }
+        ImageFormat::WebP => extract_webp_icc_profile(data),
+        _ => None,
+    }
+}
+
+/// JPEG splits profiles across APP2 segments, each tagged with its sequence number
+fn extract_jpeg_icc_profile(data: &[u8]) -> Option<Vec<u8>> {
+    const ICC_MARKER: &[u8] = b"ICC_PROFILE\0";
+    let mut chunks: Vec<(u8, &[u8])> = Vec::new();
+    let mut offset = 2;
+    while offset + 4 <= data.len() && data[offset] == 0xFF {
+        let marker = data[offset + 1];
+        // Start of scan; no more metadata segments follow
+        if marker == 0xDA {
+            break;
+        }
+        let segment_length = u16::from_be_bytes([data[offset + 2], data[offset + 3]]) as usize;
+        let segment = data.get(offset + 4..offset + 2 + segment_length)?;
+        if marker == 0xE2 && segment.starts_with(ICC_MARKER) && segment.len() > 14 {
+            chunks.push((segment[12], &segment[14..]));
+        }
+        offset += 2 + segment_length;
+    }
+
+    if chunks.is_empty() {
+        return None;
+    }
+    chunks.sort_by_key(|(sequence_number, _)| *sequence_number);
+    Some(
+        chunks
+            .into_iter()
+            .flat_map(|(_, chunk)| chunk.to_vec())
+            .collect(),
+    )
+}
+
+fn extract_webp_icc_profile(data: &[u8]) -> Option<Vec<u8>> {
+    if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WEBP" {
+        return None;
+    }
+    let mut offset = 12;
+    while offset + 8 <= data.len() {
+        let chunk_id = &data[offset..offset + 4];
+        let chunk_size = u32::from_le_bytes([
+            data[offset + 4],
+            data[offset + 5],
+            data[offset + 6],
+            data[offset + 7],
+        ]) as usize;
+        let chunk_data = data.get(offset + 8..offset + 8 + chunk_size)?;
+        if chunk_id == b"ICCP" {
+            return Some(chunk_data.to_vec());
+        }
+        // Chunks are padded to an even size
+        offset += 8 + chunk_size + (chunk_size & 1);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::ImageOutputFormat;
+
+    const SAMPLES_DIRECTORY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/samples");
+
+    /// Sample files and the size each should decode to, for the formats enabled in this build
+    const SAMPLE_FILES: &[(&str, u32, u32)] = &[
+        #[cfg(feature = "qoi")]
+        ("sample.qoi", 16, 8),
+        #[cfg(feature = "jxl")]
+        ("sample.jxl", 8, 8),
+        #[cfg(feature = "heif")]
+        ("sample.heic", 16, 8),
+        #[cfg(any(feature = "avif", feature = "heif"))]
+        ("sample.avif", 16, 8),
+    ];
+
+    fn gradient_image() -> RgbaImage {
+        RgbaImage::from_fn(16, 8, |x, y| {
+            Rgba([(x * 16) as u8, (y * 32) as u8, 128, 255 - (x * 8) as u8])
+        })
+    }
+
+    /// Writes the data to a uniquely named file so the loader sees a real path and extension
+    fn load_from_temp_file(name: &str, data: &[u8]) -> DecodedImage {
+        let path =
+            std::env::temp_dir().join(format!("foxfire-test-{}-{}", std::process::id(), name));
+        std::fs::write(&path, data).unwrap();
+        let result = load_image_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        result.unwrap()
+    }
+
+    fn encode(image: &RgbaImage, format: ImageOutputFormat) -> Vec<u8> {
+        let mut buffer = Cursor::new(Vec::new());
+        DynamicImage::ImageRgba8(image.clone())
+            .write_to(&mut buffer, format)
+            .unwrap();
+        buffer.into_inner()
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let source = gradient_image();
+        let decoded = load_from_temp_file("sample.png", &encode(&source, ImageOutputFormat::Png));
+        assert_eq!(decoded.pages.len(), 1);
+        assert_eq!(decoded.pages[0].image.to_rgba8(), source);
+    }
+
+    #[test]
+    fn jpeg_round_trip() {
+        let source = gradient_image();
+        let data = encode(&source, ImageOutputFormat::Jpeg(90));
+        let decoded = load_from_temp_file("sample.jpg", &data);
+        assert_eq!(decoded.pages[0].image.width(), 16);
+        assert_eq!(decoded.pages[0].image.height(), 8);
+    }
+
+    #[test]
+    fn tiff_round_trip() {
+        let source = gradient_image();
+        let decoded = load_from_temp_file("sample.tiff", &encode(&source, ImageOutputFormat::Tiff));
+        assert_eq!(decoded.pages[0].image.to_rgba8(), source);
+    }
+
+    #[test]
+    fn exr_layers_without_color() {
+        use exr::prelude::*;
+
+        // A render pass file with a color layer and a depth layer that has no RGB channels
+        let size = Vec2(16, 8);
+        let color = Layer::new(
+            size,
+            LayerAttributes::named("beauty"),
+            Encoding::FAST_LOSSLESS,
+            AnyChannels::sort(SmallVec::from_vec(vec![
+                AnyChannel::new("R", FlatSamples::F32(vec![0.5; 128])),
+                AnyChannel::new("G", FlatSamples::F32(vec![0.25; 128])),
+                AnyChannel::new("B", FlatSamples::F32(vec![2.; 128])),
+            ])),
+        );
+        let depth = Layer::new(
+            size,
+            LayerAttributes::named("depth"),
+            Encoding::FAST_LOSSLESS,
+            AnyChannels::sort(SmallVec::from_vec(vec![AnyChannel::new(
+                "Z",
+                FlatSamples::F32(vec![3.; 128]),
+            )])),
+        );
+        let exr_image = Image::from_layers(
+            ImageAttributes::new(IntegerBounds::from_dimensions(size)),
+            vec![color, depth],
+        );
+        let mut buffer = Cursor::new(Vec::new());
+        exr_image.write().to_buffered(&mut buffer).unwrap();
+
+        let pages = decode_exr_layers(buffer.get_ref()).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].label.as_deref(), Some("beauty"));
+        assert_eq!(
+            pages[0].image.to_rgba32f().get_pixel(3, 2).0,
+            [0.5, 0.25, 2., 1.]
+        );
+    }
+
+    #[test]
+    fn bmp_round_trip() {
+        let source = gradient_image();
+        let decoded = load_from_temp_file("sample.bmp", &encode(&source, ImageOutputFormat::Bmp));
+        // Alpha support varies between BMP header versions, so only the color is compared
+        let source_rgb = DynamicImage::ImageRgba8(source).to_rgb8();
+        assert_eq!(decoded.pages[0].image.to_rgb8(), source_rgb);
+    }
+
+    #[cfg(feature = "qoi")]
+    #[test]
+    fn qoi_round_trip() {
+        let source = gradient_image();
+        let data = qoi::encode_to_vec(source.as_raw(), source.width(), source.height()).unwrap();
+        let decoded = load_from_temp_file("sample.qoi", &data);
+        assert_eq!(decoded.pages[0].image.to_rgba8(), source);
+    }
+
+    #[test]
+    fn dds_mip_chain() {
+        // An uncompressed 4×4 RGBA texture with three mip levels
+        let mut data = vec![0u8; 128];
+        data[0..4].copy_from_slice(b"DDS ");
+        data[4..8].copy_from_slice(&124u32.to_le_bytes());
+        data[8..12].copy_from_slice(&0x2100fu32.to_le_bytes());
+        data[12..16].copy_from_slice(&4u32.to_le_bytes());
+        data[16..20].copy_from_slice(&4u32.to_le_bytes());
+        data[28..32].copy_from_slice(&3u32.to_le_bytes());
+        data[76..80].copy_from_slice(&32u32.to_le_bytes());
+        data[80..84].copy_from_slice(&0x41u32.to_le_bytes());
+        data[88..92].copy_from_slice(&32u32.to_le_bytes());
+        data[92..96].copy_from_slice(&0x000000ffu32.to_le_bytes());
+        data[104..108].copy_from_slice(&0xff000000u32.to_le_bytes());
+        for level_pixels in [16, 4, 1] {
+            for pixel in 0..level_pixels {
+                data.extend_from_slice(&[pixel as u8, 64, 128, 255]);
+            }
+        }
+
+        let decoded = load_from_temp_file("sample.dds", &data);
+        let page = &decoded.pages[0];
+        assert_eq!(page.image.width(), 4);
+        assert_eq!(page.mip_levels.len(), 2);
+        assert_eq!(page.mip_levels[1].width(), 1);
+        assert_eq!(page.image.to_rgba8().get_pixel(1, 0).0, [1, 64, 128, 255]);
+    }
+
+    #[test]
+    fn dds_bogus_counts() {
+        // A 4×4 DX10 texture claiming far more mip levels and array slices than it holds
+        let mut data = vec![0u8; 148];
+        data[0..4].copy_from_slice(b"DDS ");
+        data[4..8].copy_from_slice(&124u32.to_le_bytes());
+        data[8..12].copy_from_slice(&0x2100fu32.to_le_bytes());
+        data[12..16].copy_from_slice(&4u32.to_le_bytes());
+        data[16..20].copy_from_slice(&4u32.to_le_bytes());
+        data[28..32].copy_from_slice(&u32::MAX.to_le_bytes());
+        data[76..80].copy_from_slice(&32u32.to_le_bytes());
+        data[80..84].copy_from_slice(&0x4u32.to_le_bytes());
+        data[84..88].copy_from_slice(b"DX10");
+        data[128..132].copy_from_slice(&28u32.to_le_bytes());
+        data[140..144].copy_from_slice(&u32::MAX.to_le_bytes());
+        data.extend_from_slice(&[0u8; 64 + 16 + 4]);
+        assert!(crate::dds::decode_dds(&data).is_err());
+
+        // With a single slice the mip chain stops at 1×1
+        data[140..144].copy_from_slice(&1u32.to_le_bytes());
+        let decoded = crate::dds::decode_dds(&data).unwrap();
+        assert_eq!(decoded.pages[0].mip_levels.len(), 2);
+    }
+
+    #[test]
+    fn dds_bad_dimensions() {
+        // BC6H textures with no width, and with a width that overflows when padded to blocks
+        for width in [0, u32::MAX - 1] {
+            let mut data = vec![0u8; 148];
+            data[0..4].copy_from_slice(b"DDS ");
+            data[4..8].copy_from_slice(&124u32.to_le_bytes());
+            data[8..12].copy_from_slice(&0x100fu32.to_le_bytes());
+            data[12..16].copy_from_slice(&4u32.to_le_bytes());
+            data[16..20].copy_from_slice(&width.to_le_bytes());
+            data[76..80].copy_from_slice(&32u32.to_le_bytes());
+            data[80..84].copy_from_slice(&0x4u32.to_le_bytes());
+            data[84..88].copy_from_slice(b"DX10");
+            data[128..132].copy_from_slice(&95u32.to_le_bytes());
+            data[140..144].copy_from_slice(&1u32.to_le_bytes());
+            data.extend_from_slice(&[0u8; 1024]);
+            assert!(crate::dds::decode_dds(&data).is_err(), "width {}", width);
+        }
+    }
+
+    #[test]
+    fn svg_document() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="24" height="12">
+            <rect width="24" height="12" fill="red"/>
+        </svg>"#;
+        let decoded = load_from_temp_file("sample.svg", svg);
+        assert!(decoded.vector_source.is_some());
+        assert_eq!(decoded.pages[0].image.width(), 24);
+        assert_eq!(
+            decoded.pages[0].image.to_rgba8().get_pixel(12, 6).0,
+            [255, 0, 0, 255]
+        );
+    }
+
+    /// Decodes each file in tests/samples, which holds files for formats that have no encoder to
+    /// generate them with. A missing file is a failure, so a format can't silently go untested.
+    #[test]
+    fn sample_files() {
+        for (name, width, height) in SAMPLE_FILES {
+            let path = Path::new(SAMPLES_DIRECTORY).join(name);
+            assert!(path.is_file(), "Missing sample file {}", path.display());
+            let decoded = load_image_file(&path)
+                .unwrap_or_else(|error| panic!("Failed to decode {}: {}", path.display(), error));
+            let image = &decoded.pages[decoded.page_index].image;
+            assert_eq!(
+                (image.width(), image.height()),
+                (*width, *height),
+                "{} decoded to the wrong size",
+                path.display()
+            );
+        }
+    }
+}
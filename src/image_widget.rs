@@ -1,21 +1,56 @@
-use druid::piet::{ImageFormat, InterpolationMode};
+use druid::kurbo::Line;
+use druid::piet::{Image, ImageFormat, InterpolationMode, PietImage};
 use druid::widget::prelude::*;
-use druid::{Color, Cursor, LocalizedString, Menu, MenuItem, Rect};
+use druid::{Color, Cursor, LocalizedString, Menu, MenuItem, Point, Rect};
 use image::EncodableLayout;
+use std::path::PathBuf;
 use std::time::Instant;
 
 use crate::app_state::*;
+use crate::color_management::ColorManagement;
 use crate::events::*;
-use crate::image_container::ImageState;
+use crate::image_container::{ImageContainer, ImageState};
 use crate::types::*;
+use crate::vector_image::{parse_svg, rasterize_svg_region};
+
+// Zoom level past which individual pixel boundaries are drawn (800%)
+const PIXEL_GRID_MIN_SCALE: f64 = 8.0;
+// Minimum on-screen spacing between tile grid lines, in pixels
+const TILE_GRID_MIN_SPACING: f64 = 4.0;
+// Side length of a single checkerboard square, and of the repeated checkerboard tile
+const CHECKERBOARD_SQUARE_SIZE: usize = 8;
+const CHECKERBOARD_TILE_SIZE: usize = 256;
+
+/// A sharp rendering of the visible part of a vector image at the current zoom level
+struct VectorRaster {
+    image: PietImage,
+    source_timestamp: Instant,
+    color_revision: u32,
+    scale: f64,
+    region: Rect,
+}
+
+/// The parsed document of the current vector image, so zooming and panning don't parse it again
+struct VectorTree {
+    tree: usvg::Tree,
+    source_timestamp: Instant,
+}
 
 pub struct ImageWidget {
     transform: Option<ImageTransformation>,
+    checkerboard_cache: Option<(bool, PietImage)>,
+    vector_cache: Option<VectorRaster>,
+    vector_tree: Option<VectorTree>,
 }
 
 impl ImageWidget {
     pub fn new() -> Self {
-        Self { transform: None }
+        Self {
+            transform: None,
+            checkerboard_cache: None,
+            vector_cache: None,
+            vector_tree: None,
+        }
     }
 
     pub fn fit_image(&mut self, image: Size, container: Size, unscaled_toolbar_offset: f64) {
@@ -72,6 +107,30 @@ impl ImageWidget {
         self.transform = Some(new_transform);
     }
 
+    pub fn scale_image(
+        &mut self,
+        image: Size,
+        container: Size,
+        unscaled_toolbar_offset: f64,
+        scale_factor: f64,
+    ) {
+        let image_center: Vec2D<f64> = Vec2D::from(
+            image.width * scale_factor / 2.,
+            image.height * scale_factor / 2.,
+        );
+        let container_center: Vec2D<f64> = Vec2D::from(
+            container.width / 2.,
+            (container.height - unscaled_toolbar_offset) / 2.,
+        );
+
+        let mut new_transform = ImageTransformation::new();
+
+        new_transform.set_offset(container_center - image_center);
+        new_transform.set_scale(scale_factor);
+
+        self.transform = Some(new_transform);
+    }
+
     pub fn zoom_image(&mut self, container: Size, unscaled_toolbar_offset: f64) {
         let transform = self.transform.expect("Bad state");
         let old_scale_factor = 1. / transform.get_scale();
@@ -95,6 +154,181 @@ impl ImageWidget {
 
         self.transform = Some(new_transform);
     }
+
+    /// Renders the visible part of a vector image at the current scale, with some margin so that
+    /// small pans don't need a new rendering. Returns false if nothing could be drawn.
+    fn paint_vector_image(
+        &mut self,
+        ctx: &mut PaintCtx,
+        image_container: &ImageContainer,
+        vector_source: &[u8],
+        scale: f64,
+        image_origin: Vec2D<f64>,
+        color_management: &ColorManagement,
+    ) -> bool {
+        let container_rect = ctx.size().to_rect();
+        let image_rect = image_container.get_size().to_rect();
+        let visible_region = Rect::new(
+            (container_rect.x0 - image_origin.x) / scale,
+            (container_rect.y0 - image_origin.y) / scale,
+            (container_rect.x1 - image_origin.x) / scale,
+            (container_rect.y1 - image_origin.y) / scale,
+        )
+        .intersect(image_rect);
+        if visible_region.area() <= 0. {
+            return true;
+        }
+
+        let source_timestamp = *image_container.get_timestamp();
+        let color_revision = color_management.get_revision();
+        let is_cached = matches!(
+            &self.vector_cache,
+            Some(cache) if cache.source_timestamp == source_timestamp
+                && cache.color_revision == color_revision
+                && cache.scale == scale
+                && cache.region.union(visible_region) == cache.region
+        );
+        if !is_cached {
+            let is_parsed = matches!(
+                &self.vector_tree,
+                Some(vector_tree) if vector_tree.source_timestamp == source_timestamp
+            );
+            if !is_parsed {
+                self.vector_tree = parse_svg(vector_source).map(|tree| VectorTree {
+                    tree,
+                    source_timestamp,
+                });
+            }
+            let tree = match &self.vector_tree {
+                Some(vector_tree) => &vector_tree.tree,
+                None => return false,
+            };
+            let region = visible_region
+                .inflate(visible_region.width() / 2., visible_region.height() / 2.)
+                .intersect(image_rect);
+            let mut rgba = match rasterize_svg_region(tree, region, scale) {
+                Some(rgba) => rgba,
+                None => return false,
+            };
+            color_management.convert_for_display(&mut rgba, None);
+            let image = match ctx.make_image(
+                rgba.width() as usize,
+                rgba.height() as usize,
+                rgba.as_bytes(),
+                ImageFormat::RgbaSeparate,
+            ) {
+                Ok(image) => image,
+                Err(_) => return false,
+            };
+            self.vector_cache = Some(VectorRaster {
+                image,
+                source_timestamp,
+                color_revision,
+                scale,
+                region,
+            });
+        }
+
+        let cache = self.vector_cache.as_ref().unwrap();
+        let destination = Rect::from_origin_size(
+            (
+                image_origin.x + cache.region.x0 * scale,
+                image_origin.y + cache.region.y0 * scale,
+            ),
+            cache.image.size(),
+        );
+        ctx.draw_image(&cache.image, destination, InterpolationMode::Bilinear);
+        true
+    }
+
+    fn paint_checkerboard(&mut self, ctx: &mut PaintCtx, region: Rect, dark_theme_enabled: bool) {
+        let is_cache_valid =
+            matches!(&self.checkerboard_cache, Some((dark, _)) if *dark == dark_theme_enabled);
+        if !is_cache_valid {
+            let (light_square, dark_square): (u8, u8) = if dark_theme_enabled {
+                (64, 40)
+            } else {
+                (255, 204)
+            };
+            let mut tile_data = Vec::with_capacity(CHECKERBOARD_TILE_SIZE * CHECKERBOARD_TILE_SIZE);
+            for y in 0..CHECKERBOARD_TILE_SIZE {
+                for x in 0..CHECKERBOARD_TILE_SIZE {
+                    let is_light =
+                        (x / CHECKERBOARD_SQUARE_SIZE + y / CHECKERBOARD_SQUARE_SIZE) % 2 == 0;
+                    tile_data.push(if is_light { light_square } else { dark_square });
+                }
+            }
+            let tile_result = ctx.make_image(
+                CHECKERBOARD_TILE_SIZE,
+                CHECKERBOARD_TILE_SIZE,
+                &tile_data,
+                ImageFormat::Grayscale,
+            );
+            match tile_result {
+                Ok(tile) => self.checkerboard_cache = Some((dark_theme_enabled, tile)),
+                Err(_) => return,
+            }
+        }
+        let tile = &self.checkerboard_cache.as_ref().unwrap().1;
+
+        // The pattern is anchored to the widget, so it stays put while the image is panned
+        let region = region.intersect(ctx.size().to_rect());
+        if region.area() <= 0. {
+            return;
+        }
+        let tile_size = CHECKERBOARD_TILE_SIZE as f64;
+        ctx.with_save(|ctx| {
+            ctx.clip(region);
+            let mut y = (region.y0 / tile_size).floor() * tile_size;
+            while y < region.y1 {
+                let mut x = (region.x0 / tile_size).floor() * tile_size;
+                while x < region.x1 {
+                    let tile_rect = Rect::new(x, y, x + tile_size, y + tile_size);
+                    ctx.draw_image(tile, tile_rect, InterpolationMode::NearestNeighbor);
+                    x += tile_size;
+                }
+                y += tile_size;
+            }
+        });
+    }
+
+    fn paint_grid(
+        ctx: &mut PaintCtx,
+        image_size: Size,
+        viewport: Rect,
+        cell_size: f64,
+        color: &Color,
+        stroke_width: f64,
+    ) {
+        let scale = viewport.width() / image_size.width;
+        let spacing = cell_size * scale;
+        if spacing < TILE_GRID_MIN_SPACING {
+            return;
+        }
+
+        // Only draw the lines that fall inside both the image and the widget
+        let visible = viewport.intersect(ctx.size().to_rect());
+        if visible.area() <= 0. {
+            return;
+        }
+        let first_column = ((visible.x0 - viewport.x0) / spacing).ceil() as u64;
+        let last_column = ((visible.x1 - viewport.x0) / spacing).floor() as u64;
+        let first_row = ((visible.y0 - viewport.y0) / spacing).ceil() as u64;
+        let last_row = ((visible.y1 - viewport.y0) / spacing).floor() as u64;
+
+        // Offset by half a pixel so that one pixel wide lines stay crisp
+        let align = |v: f64| v.round() + 0.5;
+        for column in first_column..=last_column {
+            let x = align(viewport.x0 + column as f64 * spacing);
+            let line = Line::new(Point::new(x, visible.y0), Point::new(x, visible.y1));
+            ctx.stroke(line, color, stroke_width);
+        }
+        for row in first_row..=last_row {
+            let y = align(viewport.y0 + row as f64 * spacing);
+            let line = Line::new(Point::new(visible.x0, y), Point::new(visible.x1, y));
+            ctx.stroke(line, color, stroke_width);
+        }
+    }
 }
 
 impl Widget<AppState> for ImageWidget {
@@ -102,6 +336,15 @@ impl Widget<AppState> for ImageWidget {
         let has_image = data.has_image();
         let has_image_error = data.has_image_error();
 
+        // Ctrl-dragging takes the image out to other applications instead of panning it
+        if let Event::MouseDown(mouse_event) = event {
+            if has_image && mouse_event.button.is_left() && mouse_event.mods.ctrl() {
+                data.start_image_drag();
+                ctx.set_handled();
+                return;
+            }
+        }
+
         let image_state_guard = data.get_image_ref();
         let image_state = &mut *image_state_guard.lock().unwrap();
         if let ImageState::Loaded(image_container) = image_state {
@@ -125,7 +368,14 @@ impl Widget<AppState> for ImageWidget {
                         // _ctx.set_cursor(&Cursor::Crosshair);
                         // self.set_centered_state(false);
                     } else if mouse_event.button.is_right() {
-                        let context_menu = generate_menu(has_image, has_image_error);
+                        data.prune_recent_paths();
+                        let context_menu = generate_menu(
+                            has_image,
+                            has_image_error,
+                            data.get_channel_view(),
+                            image_container.is_raw_preview(),
+                            data.get_recent_paths(),
+                        );
                         ctx.show_context_menu(context_menu, mouse_event.pos)
                     }
                 }
@@ -150,7 +400,14 @@ impl Widget<AppState> for ImageWidget {
             }
         } else if let Event::MouseDown(mouse_event) = event {
             if mouse_event.button.is_right() {
-                let context_menu = generate_menu(has_image, has_image_error);
+                data.prune_recent_paths();
+                let context_menu = generate_menu(
+                    has_image,
+                    has_image_error,
+                    data.get_channel_view(),
+                    false,
+                    data.get_recent_paths(),
+                );
                 ctx.show_context_menu(context_menu, mouse_event.pos)
             }
         }
@@ -163,7 +420,11 @@ impl Widget<AppState> for ImageWidget {
         _data: &AppState,
         _env: &Env,
     ) {
+        // A window that has been placed before keeps its saved size
         if let LifeCycle::WidgetAdded = _event {
+            if _data.has_window_geometry() {
+                return;
+            }
             let image_state_guard = _data.get_image_ref();
             let image_state = &mut *image_state_guard.lock().unwrap();
             if let ImageState::Loaded(image_container) = image_state {
@@ -232,7 +493,7 @@ impl Widget<AppState> for ImageWidget {
                 let container_size = bc.max();
                 let toolbar_height = data.get_toolbar_height();
                 self.realsize_image(image_size, container_size, toolbar_height);
-            } 
+            }
             // else {
             //     let image_size = image_container.get_size();
             //     let container_size = bc.max();
@@ -254,26 +515,30 @@ impl Widget<AppState> for ImageWidget {
         let container_size = ctx.size();
         let container_rect = container_size.to_rect();
 
-        if data.dark_theme_enabled {
-            ctx.fill(container_rect, &Color::BLACK);
-        } else {
-            ctx.fill(container_rect, &Color::WHITE);
-        }
+        let background_color = match data.get_canvas_background() {
+            CanvasBackground::Solid(color) => color.clone(),
+            _ if data.dark_theme_enabled => Color::BLACK,
+            _ => Color::WHITE,
+        };
+        ctx.fill(container_rect, &background_color);
 
         let image_state_guard = data.get_image_ref();
         let image_state = &mut *image_state_guard.lock().unwrap();
         if let ImageState::Loaded(image_container) = image_state {
             let image_size = image_container.get_size();
 
-            if !image_container.has_cache() {
-                let image_rgba = image_container.get_image().clone().into_rgba8();
+            let render_settings = data.get_render_settings();
+            let channel_view = render_settings.channel_view;
+            if !image_container.has_cache_for(&render_settings) {
+                let image_rgba =
+                    image_container.get_display_rgba(&render_settings, data.get_color_management());
                 let image_result = ctx.make_image(
                     image_size.width as usize,
                     image_size.height as usize,
                     image_rgba.as_bytes(),
                     ImageFormat::RgbaSeparate,
                 );
-                image_container.set_cache(image_result.unwrap());
+                image_container.set_cache(image_result.unwrap(), render_settings);
             }
 
             if self.transform.is_none() {
@@ -333,27 +598,102 @@ impl Widget<AppState> for ImageWidget {
             );
             self.transform = Some(image_transform);
 
+            if let CanvasBackground::Checkerboard = data.get_canvas_background() {
+                if image_container.has_alpha() && channel_view == ChannelView::Composite {
+                    self.paint_checkerboard(ctx, container_viewport, data.dark_theme_enabled);
+                }
+            }
+
             let interpolation_mode = match data.image_filtering_enabled() {
                 true => InterpolationMode::Bilinear,
                 _ => InterpolationMode::NearestNeighbor,
             };
-            ctx.draw_image_area(
-                image_container.get_cache().unwrap(),
-                image_viewport,
-                container_viewport,
-                interpolation_mode,
-            );
+            // Vector images are rendered afresh for the current zoom level, with the bitmap
+            // rendition as a fallback
+            let vector_source = image_container.get_vector_source().cloned();
+            let painted_vector = match vector_source {
+                Some(vector_source) if channel_view == ChannelView::Composite => self
+                    .paint_vector_image(
+                        ctx,
+                        image_container,
+                        &vector_source,
+                        image_transform.get_scale(),
+                        image_origin_screenspace,
+                        data.get_color_management(),
+                    ),
+                _ => false,
+            };
+            if !painted_vector {
+                ctx.draw_image_area(
+                    image_container.get_cache().unwrap(),
+                    image_viewport,
+                    container_viewport,
+                    interpolation_mode,
+                );
+            }
+
+            // Pixel boundaries are only meaningful when pixels are drawn as hard-edged squares
+            if data.pixel_grid_enabled()
+                && !data.image_filtering_enabled()
+                && image_transform.get_scale() >= PIXEL_GRID_MIN_SCALE
+            {
+                let grid_color = Color::rgba8(128, 128, 128, 160);
+                Self::paint_grid(ctx, image_size, container_viewport, 1., &grid_color, 1.);
+            }
+            if let Some(tile_size) = data.tile_grid_size() {
+                let grid_color = Color::rgba8(208, 135, 112, 200);
+                Self::paint_grid(
+                    ctx,
+                    image_size,
+                    container_viewport,
+                    tile_size as f64,
+                    &grid_color,
+                    1.,
+                );
+            }
         }
     }
 }
 
-fn generate_menu(has_image: bool, has_image_error: bool) -> Menu<AppState> {
+fn generate_menu(
+    has_image: bool,
+    has_image_error: bool,
+    channel_view: ChannelView,
+    is_raw_preview: bool,
+    recent_paths: &[PathBuf],
+) -> Menu<AppState> {
     let has_image_loaded = has_image && !has_image_error;
+
+    let mut recent_menu = Menu::new(LocalizedString::new("Open recent"));
+    for path in recent_paths {
+        let path = path.clone();
+        recent_menu = recent_menu.entry(
+            MenuItem::new(path.to_string_lossy().to_string())
+                .on_activate(move |_ctx, data: &mut AppState, _env| data.open_recent_path(&path)),
+        );
+    }
+    recent_menu = recent_menu.separator().entry(
+        MenuItem::new(LocalizedString::new("Clear recent"))
+            .on_activate(|_ctx, data: &mut AppState, _env| data.clear_recent_paths())
+            .enabled(!recent_paths.is_empty()),
+    );
+
+    let mut channel_menu = Menu::new(LocalizedString::new("View channel"));
+    for view in ChannelView::ALL {
+        channel_menu = channel_menu.entry(
+            MenuItem::new(LocalizedString::new(view.label()))
+                .selected(view == channel_view)
+                .on_activate(move |_ctx, data: &mut AppState, _env| data.set_channel_view(view))
+                .enabled(has_image_loaded),
+        );
+    }
+
     Menu::empty()
         .entry(
             MenuItem::new(LocalizedString::new("Open new image"))
                 .on_activate(|_ctx, data: &mut AppState, _env| data.show_file_load_dialog()),
         )
+        .entry(recent_menu)
         .entry(
             MenuItem::new(LocalizedString::new("Open current image with..."))
                 .on_activate(|_ctx, data: &mut AppState, _env| data.open_with())
@@ -386,6 +726,14 @@ fn generate_menu(has_image: bool, has_image_error: bool) -> Menu<AppState> {
                 })
                 .enabled(has_image_loaded),
         )
+        .entry(channel_menu)
+        .entry(
+            MenuItem::new(LocalizedString::new("Develop RAW"))
+                .on_activate(|_ctx, data: &mut AppState, _env| {
+                    data.develop_raw_image(&Instant::now())
+                })
+                .enabled(is_raw_preview),
+        )
         .separator()
         .entry(
             MenuItem::new(LocalizedString::new("Copy"))
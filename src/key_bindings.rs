@@ -0,0 +1,202 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use druid::{Data, KbKey, KeyEvent, Modifiers};
+
+/// Everything that can be bound to a key. Escape always leaves fullscreen and stops a
+/// slideshow, so it isn't listed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum KeyAction {
+    NextImage,
+    PrevImage,
+    OpenFile,
+    NextPage,
+    PrevPage,
+    NextMipLevel,
+    PrevMipLevel,
+    ToggleCubeCross,
+    ToggleHistogram,
+    CycleChannelView,
+    ToggleFullscreen,
+    ToggleSlideshow,
+    DevelopRaw,
+}
+
+impl KeyAction {
+    pub const ALL: [KeyAction; 13] = [
+        KeyAction::NextImage,
+        KeyAction::PrevImage,
+        KeyAction::OpenFile,
+        KeyAction::NextPage,
+        KeyAction::PrevPage,
+        KeyAction::NextMipLevel,
+        KeyAction::PrevMipLevel,
+        KeyAction::ToggleCubeCross,
+        KeyAction::ToggleHistogram,
+        KeyAction::CycleChannelView,
+        KeyAction::ToggleFullscreen,
+        KeyAction::ToggleSlideshow,
+        KeyAction::DevelopRaw,
+    ];
+
+    /// The name used in the settings file
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyAction::NextImage => "next_image",
+            KeyAction::PrevImage => "prev_image",
+            KeyAction::OpenFile => "open_file",
+            KeyAction::NextPage => "next_page",
+            KeyAction::PrevPage => "prev_page",
+            KeyAction::NextMipLevel => "next_mip_level",
+            KeyAction::PrevMipLevel => "prev_mip_level",
+            KeyAction::ToggleCubeCross => "toggle_cube_cross",
+            KeyAction::ToggleHistogram => "toggle_histogram",
+            KeyAction::CycleChannelView => "cycle_channel_view",
+            KeyAction::ToggleFullscreen => "toggle_fullscreen",
+            KeyAction::ToggleSlideshow => "toggle_slideshow",
+            KeyAction::DevelopRaw => "develop_raw",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            KeyAction::NextImage => "Next image",
+            KeyAction::PrevImage => "Previous image",
+            KeyAction::OpenFile => "Open file",
+            KeyAction::NextPage => "Next page",
+            KeyAction::PrevPage => "Previous page",
+            KeyAction::NextMipLevel => "Next mip level",
+            KeyAction::PrevMipLevel => "Previous mip level",
+            KeyAction::ToggleCubeCross => "Cube map cross layout",
+            KeyAction::ToggleHistogram => "Histogram",
+            KeyAction::CycleChannelView => "Cycle channels",
+            KeyAction::ToggleFullscreen => "Fullscreen",
+            KeyAction::ToggleSlideshow => "Slideshow",
+            KeyAction::DevelopRaw => "Develop RAW",
+        }
+    }
+
+    pub fn default_binding(&self) -> &'static str {
+        match self {
+            KeyAction::NextImage => "ArrowRight",
+            KeyAction::PrevImage => "ArrowLeft",
+            KeyAction::OpenFile => "Ctrl+o",
+            KeyAction::NextPage => "PageDown",
+            KeyAction::PrevPage => "PageUp",
+            KeyAction::NextMipLevel => "]",
+            KeyAction::PrevMipLevel => "[",
+            KeyAction::ToggleCubeCross => "x",
+            KeyAction::ToggleHistogram => "h",
+            KeyAction::CycleChannelView => "c",
+            KeyAction::ToggleFullscreen => "F11",
+            KeyAction::ToggleSlideshow => "s",
+            KeyAction::DevelopRaw => "d",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        KeyAction::ALL
+            .into_iter()
+            .find(|action| action.name() == name)
+    }
+}
+
+/// Keys as written in the settings, such as "ArrowRight", "Ctrl+o" or "]"
+#[derive(Clone, Data)]
+pub struct KeyBindings {
+    bindings: Arc<BTreeMap<KeyAction, String>>,
+}
+
+impl KeyBindings {
+    pub fn new() -> Self {
+        Self {
+            bindings: Arc::new(
+                KeyAction::ALL
+                    .into_iter()
+                    .map(|action| (action, action.default_binding().to_string()))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Reads bindings by action name, keeping the default for anything missing or unusable
+    pub fn from_settings(settings: &BTreeMap<String, String>) -> (Self, Vec<String>) {
+        let mut key_bindings = Self::new();
+        let mut errors = Vec::new();
+        for (name, binding) in settings {
+            match KeyAction::from_name(name) {
+                Some(action) if parse_binding(binding).is_some() => {
+                    key_bindings.set(action, binding.clone())
+                }
+                Some(_) => errors.push(format!("{}: \"{}\" isn't a key", name, binding)),
+                None => errors.push(format!("{}: unknown action", name)),
+            }
+        }
+        (key_bindings, errors)
+    }
+
+    pub fn to_settings(&self) -> BTreeMap<String, String> {
+        self.bindings
+            .iter()
+            .map(|(action, binding)| (action.name().to_string(), binding.clone()))
+            .collect()
+    }
+
+    pub fn get(&self, action: KeyAction) -> String {
+        self.bindings.get(&action).cloned().unwrap_or_default()
+    }
+
+    pub fn set(&mut self, action: KeyAction, binding: String) {
+        Arc::make_mut(&mut self.bindings).insert(action, binding);
+    }
+
+    pub fn is_valid(&self, action: KeyAction) -> bool {
+        parse_binding(&self.get(action)).is_some()
+    }
+
+    pub fn find_action(&self, event: &KeyEvent) -> Option<KeyAction> {
+        self.bindings.iter().find_map(|(action, binding)| {
+            let (key, mods) = parse_binding(binding)?;
+            let is_match = match (&event.key, &key) {
+                // Letters are compared ignoring case, with Shift as written in the binding
+                (KbKey::Character(typed), KbKey::Character(bound)) => {
+                    // Symbols like "}" take Shift to type, so it only counts when asked for
+                    let event_mods = if mods.shift() || typed.chars().any(char::is_alphabetic) {
+                        event.mods
+                    } else {
+                        event.mods - Modifiers::SHIFT
+                    };
+                    typed.to_lowercase() == *bound && event_mods == mods
+                }
+                _ => event.key == key && event.mods == mods,
+            };
+            is_match.then_some(*action)
+        })
+    }
+}
+
+fn parse_binding(binding: &str) -> Option<(KbKey, Modifiers)> {
+    let mut parts: Vec<&str> = binding.split('+').collect();
+    // A binding of "+" itself, or one ending in "++", is for the plus key
+    if binding.ends_with("++") || binding == "+" {
+        parts.truncate(parts.len().saturating_sub(2));
+        parts.push("+");
+    }
+    let (key_name, modifier_names) = parts.split_last()?;
+    let mut mods = Modifiers::empty();
+    for modifier_name in modifier_names {
+        mods |= match modifier_name.to_ascii_lowercase().as_str() {
+            "ctrl" | "control" => Modifiers::CONTROL,
+            "shift" => Modifiers::SHIFT,
+            "alt" => Modifiers::ALT,
+            "meta" | "cmd" | "super" => Modifiers::META,
+            _ => return None,
+        };
+    }
+    match KbKey::from_str(key_name).ok()? {
+        KbKey::Unidentified => None,
+        KbKey::Character(character) => Some((KbKey::Character(character.to_lowercase()), mods)),
+        key => Some((key, mods)),
+    }
+}
@@ -1,58 +1,115 @@
 #![windows_subsystem = "windows"]
-use druid::{AppLauncher, WindowDesc};
-use std::env;
+use clap::Parser;
+use druid::{AppLauncher, WindowDesc, WindowState};
 
 mod events;
 
 mod types;
 
+mod color_management;
 mod commands;
 
 use commands::*;
 
 mod button_widget;
+mod cli;
 mod container_widget;
+mod dds;
+mod exif_metadata;
+mod extended_formats;
+mod hdr_widget;
+mod headless;
+mod histogram;
+mod histogram_widget;
+mod image_cache;
 mod image_container;
+mod image_loader;
 mod image_widget;
+mod key_bindings;
 mod toolbar_widget;
+mod vector_image;
 
 mod app_state;
+mod archive;
 use app_state::*;
 
 mod osd_widget;
+mod playlist;
+mod preferences_window;
+mod raw_image;
+mod recent_widget;
+mod remote_control;
+mod settings;
+mod single_instance;
+mod system_theme;
 mod ui_builder;
 
+use cli::Cli;
+use playlist::collect_launch_paths;
+use settings::Settings;
 use ui_builder::*;
 
 fn main() {
-    // Get command line arguments
-    let args: Vec<String> = env::args().collect();
+    // Get command line arguments, along with any list files or piped paths
+    let mut cli = Cli::parse();
+    if let Some(command) = cli.command.take() {
+        std::process::exit(headless::run(command));
+    }
+    let launch_paths = collect_launch_paths(&cli.paths);
 
-    let file_name: String = if args.len() > 1 {
-        args[1].clone()
-    } else {
-        String::new()
-    };
+    // Settings come first, as the window opens where it was last closed
+    let (settings, errors) = Settings::load();
+    for error in &errors {
+        println!("Settings: {}", error);
+    }
 
     // Build the UI structure
-    let main_window = WindowDesc::new(build_ui())
+    let window_size = settings
+        .window_size
+        .map_or((640., 480.), |[width, height]| (width, height));
+    let mut main_window = WindowDesc::new(build_ui())
         .title("Foxfire - Image Viewer")
         .with_min_size((450., 240.))
-        .window_size((640., 480.));
+        .window_size(window_size);
+    if let Some([x, y]) = settings.window_position {
+        main_window = main_window.set_position((x, y));
+    }
+    if cli.fullscreen {
+        main_window = main_window
+            .set_window_state(WindowState::Maximized)
+            .show_titlebar(false);
+    } else if settings.window_maximized {
+        main_window = main_window.set_window_state(WindowState::Maximized);
+    }
     let launcher = AppLauncher::with_window(main_window).log_to_console();
 
     //Set initial state
-    let theme_state = match dark_light::detect() {
-        dark_light::Mode::Dark => true,
-        dark_light::Mode::Light => false,
-    };
+    let theme_state = system_theme::is_dark();
     let mut initial_state = AppState::from(theme_state, launcher.get_external_handle());
-    initial_state.startup(file_name);
-    initial_state.load_prefs();
+    // Preferences are applied before opening anything, as they affect how folders are scanned
+    initial_state.load_prefs(settings);
+    if initial_state.single_instance_enabled() {
+        if single_instance::forward_launch(&cli.to_option_args(), &launch_paths) {
+            return;
+        }
+        single_instance::listen(launcher.get_external_handle());
+    }
+    system_theme::watch(theme_state, launcher.get_external_handle());
+    if let Some(socket_path) = cli.control_socket.clone() {
+        remote_control::listen(socket_path, launcher.get_external_handle());
+    }
+    initial_state.apply_session_overrides(&cli);
+    if launch_paths.is_empty() {
+        initial_state.restore_last_session();
+    } else {
+        initial_state.open_launch_paths(launch_paths, cli.start_index);
+    }
 
     // Launch program
     launcher
-        .delegate(Delegate::new())
+        .delegate(Delegate::new(errors))
         .launch(initial_state)
         .expect("Failed to launch application");
+    single_instance::stop_listening();
+    remote_control::stop_listening();
 }
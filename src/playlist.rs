@@ -0,0 +1,85 @@
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+
+/// Extensions of list files whose lines are image paths
+const LIST_EXTENSIONS: [&str; 4] = ["m3u", "m3u8", "txt", "lst"];
+
+/// The paths handed over on launch, either one file or folder to browse like before, or an
+/// explicit set that replaces the folder scan
+pub enum LaunchPaths {
+    None,
+    Single(String),
+    List(Vec<PathBuf>),
+}
+
+impl LaunchPaths {
+    pub fn is_empty(&self) -> bool {
+        match self {
+            LaunchPaths::None => true,
+            LaunchPaths::Single(_) => false,
+            LaunchPaths::List(paths) => paths.is_empty(),
+        }
+    }
+}
+
+/// Collects image paths from the command line arguments, from list files among them, and from
+/// stdin when "-" is given. Launchers often leave stdin as a pipe that never closes, so it's
+/// only read when asked for.
+pub fn collect_launch_paths(args: &[String]) -> LaunchPaths {
+    match args {
+        [] => LaunchPaths::None,
+        [arg] if arg != "-" && !is_list_file(Path::new(arg)) => LaunchPaths::Single(arg.clone()),
+        _ => {
+            let mut paths = Vec::new();
+            for arg in args {
+                if arg == "-" {
+                    paths.extend(read_path_list(std::io::stdin().lock(), None));
+                } else if is_list_file(Path::new(arg)) {
+                    paths.extend(read_list_file(Path::new(arg)));
+                } else {
+                    paths.push(PathBuf::from(arg));
+                }
+            }
+            // An empty list opens nothing, the same as launching without arguments
+            match paths.is_empty() {
+                true => LaunchPaths::None,
+                false => LaunchPaths::List(paths),
+            }
+        }
+    }
+}
+
+fn is_list_file(path: &Path) -> bool {
+    path.extension()
+        .map(|extension| extension.to_ascii_lowercase())
+        .map_or(false, |extension| {
+            LIST_EXTENSIONS
+                .iter()
+                .any(|list_extension| extension == *list_extension)
+        })
+}
+
+fn read_list_file(path: &Path) -> Vec<PathBuf> {
+    match std::fs::File::open(path) {
+        Ok(file) => read_path_list(std::io::BufReader::new(file), path.parent()),
+        Err(error) => {
+            println!("Failed to read list file {}: {}", path.display(), error);
+            Vec::new()
+        }
+    }
+}
+
+/// Reads one path per line, skipping blank lines and M3U comments. Relative paths in list
+/// files are relative to the list itself, as in M3U playlists.
+fn read_path_list(reader: impl BufRead, base_folder: Option<&Path>) -> Vec<PathBuf> {
+    reader
+        .lines()
+        .map_while(Result::ok)
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| match base_folder {
+            Some(base_folder) => base_folder.join(line),
+            None => PathBuf::from(line),
+        })
+        .collect()
+}
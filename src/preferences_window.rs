@@ -0,0 +1,225 @@
+//! The preferences window. Its controls edit the shared app state directly, so every change
+//! shows up in the viewer straight away and is saved along with the rest on exit.
+
+use druid::widget::{
+    Checkbox, CrossAxisAlignment, Flex, Label, RadioGroup, Scroll, Stepper, TextBox,
+};
+use druid::{lens, Color, Widget, WidgetExt};
+
+use crate::app_state::AppState;
+use crate::key_bindings::KeyAction;
+use crate::settings::{CACHE_MEMORY_LIMIT_MB_RANGE, PREFETCH_COUNT_RANGE};
+use crate::types::{CanvasBackground, SortMode, ThemeMode, ZoomMode};
+
+const LABEL_WIDTH: f64 = 180.;
+
+pub fn build_preferences_window() -> impl Widget<AppState> {
+    let mut key_bindings = Flex::column().cross_axis_alignment(CrossAxisAlignment::Start);
+    for action in KeyAction::ALL {
+        key_bindings.add_child(build_key_binding_row(action));
+        key_bindings.add_spacer(4.);
+    }
+
+    let preferences = Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(section_heading("Display"))
+        .with_child(Label::new("Appearance"))
+        .with_child(
+            RadioGroup::column(
+                ThemeMode::ALL
+                    .into_iter()
+                    .map(|theme_mode| (theme_mode.label(), theme_mode))
+                    .collect::<Vec<_>>(),
+            )
+            .lens(lens::Map::new(
+                |data: &AppState| data.theme_mode(),
+                |data: &mut AppState, theme_mode| data.set_theme_mode(theme_mode),
+            )),
+        )
+        .with_spacer(8.)
+        .with_child(Checkbox::new("Blur behind overlays").lens(lens::Map::new(
+            |data: &AppState| data.blur_enabled(),
+            |data: &mut AppState, enabled: bool| {
+                if enabled != data.blur_enabled() {
+                    data.blur_enable_toggle();
+                }
+            },
+        )))
+        .with_spacer(4.)
+        .with_child(Checkbox::new("Smooth image filtering").lens(lens::Map::new(
+            |data: &AppState| data.image_filtering_enabled(),
+            |data: &mut AppState, enabled: bool| {
+                if enabled != data.image_filtering_enabled() {
+                    data.image_filtering_enable_toggle();
+                }
+            },
+        )))
+        .with_spacer(8.)
+        .with_child(Label::new("Background"))
+        .with_child(
+            RadioGroup::column(vec![
+                ("Follow system theme", CanvasBackground::Theme),
+                (
+                    "Checkerboard behind transparency",
+                    CanvasBackground::Checkerboard,
+                ),
+                ("Black", CanvasBackground::Solid(Color::BLACK)),
+                ("Gray", CanvasBackground::Solid(Color::rgb8(128, 128, 128))),
+                ("White", CanvasBackground::Solid(Color::WHITE)),
+            ])
+            .lens(lens::Map::new(
+                |data: &AppState| data.get_canvas_background().clone(),
+                |data: &mut AppState, background| data.set_canvas_background(background),
+            )),
+        )
+        .with_spacer(8.)
+        .with_child(Label::new("Open images at"))
+        .with_child(
+            RadioGroup::column(vec![
+                ("Fit to window", ZoomMode::Fit),
+                ("Actual size", ZoomMode::RealSize),
+            ])
+            .lens(lens::Map::new(
+                |data: &AppState| data.zoom_mode(),
+                |data: &mut AppState, zoom_mode| data.set_zoom_mode(zoom_mode),
+            )),
+        )
+        .with_spacer(16.)
+        .with_child(section_heading("Browsing"))
+        .with_child(Label::new("Sort by"))
+        .with_child(
+            RadioGroup::column(
+                SortMode::ALL
+                    .into_iter()
+                    .map(|sort_mode| (sort_mode.label(), sort_mode))
+                    .collect::<Vec<_>>(),
+            )
+            .lens(lens::Map::new(
+                |data: &AppState| data.sort_mode(),
+                |data: &mut AppState, sort_mode| data.set_sort_mode(sort_mode),
+            )),
+        )
+        .with_spacer(8.)
+        .with_child(
+            Checkbox::new("Wrap around at the ends").lens(lens::Map::new(
+                |data: &AppState| data.wrap_around_enabled(),
+                |data: &mut AppState, enabled: bool| {
+                    if enabled != data.wrap_around_enabled() {
+                        data.wrap_around_enable_toggle();
+                    }
+                },
+            )),
+        )
+        .with_spacer(4.)
+        .with_child(
+            Checkbox::new("Ask before moving to trash").lens(lens::Map::new(
+                |data: &AppState| data.confirm_delete_enabled(),
+                |data: &mut AppState, enabled: bool| {
+                    if enabled != data.confirm_delete_enabled() {
+                        data.confirm_delete_enable_toggle();
+                    }
+                },
+            )),
+        )
+        .with_spacer(4.)
+        .with_child(
+            Checkbox::new("Reopen the last image when started without files").lens(lens::Map::new(
+                |data: &AppState| data.restore_session_enabled(),
+                |data: &mut AppState, enabled: bool| {
+                    if enabled != data.restore_session_enabled() {
+                        data.restore_session_enable_toggle();
+                    }
+                },
+            )),
+        )
+        .with_spacer(8.)
+        .with_child(build_stepper_row(
+            "Slideshow interval",
+            |data: &AppState| format!("{} s", data.slideshow_delay()),
+            Stepper::new()
+                .with_range(1., 3600.)
+                .with_step(1.)
+                .lens(lens::Map::new(
+                    |data: &AppState| data.slideshow_delay(),
+                    |data: &mut AppState, delay: f64| data.set_slideshow_delay(delay),
+                )),
+        ))
+        .with_spacer(16.)
+        .with_child(section_heading("Memory"))
+        .with_child(build_stepper_row(
+            "Images to prefetch",
+            |data: &AppState| data.prefetch_count().to_string(),
+            Stepper::new()
+                .with_range(
+                    *PREFETCH_COUNT_RANGE.start() as f64,
+                    *PREFETCH_COUNT_RANGE.end() as f64,
+                )
+                .with_step(1.)
+                .lens(lens::Map::new(
+                    |data: &AppState| data.prefetch_count() as f64,
+                    |data: &mut AppState, count: f64| data.set_prefetch_count(count as usize),
+                )),
+        ))
+        .with_spacer(4.)
+        .with_child(build_stepper_row(
+            "Cache memory limit",
+            |data: &AppState| format!("{} MB", data.cache_memory_limit_mb()),
+            Stepper::new()
+                .with_range(
+                    *CACHE_MEMORY_LIMIT_MB_RANGE.start() as f64,
+                    *CACHE_MEMORY_LIMIT_MB_RANGE.end() as f64,
+                )
+                .with_step(64.)
+                .lens(lens::Map::new(
+                    |data: &AppState| data.cache_memory_limit_mb() as f64,
+                    |data: &mut AppState, limit: f64| {
+                        data.set_cache_memory_limit_mb(limit as usize)
+                    },
+                )),
+        ))
+        .with_spacer(16.)
+        .with_child(section_heading("Key bindings"))
+        .with_child(
+            Label::new("Keys are written like ArrowRight, Ctrl+o, Shift+s or ]")
+                .with_text_size(12.),
+        )
+        .with_spacer(8.)
+        .with_child(key_bindings)
+        .padding(16.);
+
+    Scroll::new(preferences).vertical()
+}
+
+fn section_heading(title: &str) -> impl Widget<AppState> {
+    Label::new(title)
+        .with_text_size(16.)
+        .padding((0., 0., 0., 8.))
+}
+
+fn build_stepper_row(
+    title: &str,
+    value_text: impl Fn(&AppState) -> String + 'static,
+    stepper: impl Widget<AppState> + 'static,
+) -> impl Widget<AppState> {
+    Flex::row()
+        .with_child(Label::new(title).fix_width(LABEL_WIDTH))
+        .with_child(Label::dynamic(move |data: &AppState, _env| value_text(data)).fix_width(80.))
+        .with_child(stepper)
+}
+
+fn build_key_binding_row(action: KeyAction) -> impl Widget<AppState> {
+    Flex::row()
+        .with_child(Label::new(action.label()).fix_width(LABEL_WIDTH))
+        .with_child(TextBox::new().fix_width(120.).lens(lens::Map::new(
+            move |data: &AppState| data.get_key_binding(action),
+            move |data: &mut AppState, binding: String| data.set_key_binding(action, binding),
+        )))
+        .with_spacer(8.)
+        // A binding that can't be read is kept while typing, but does nothing
+        .with_child(Label::dynamic(move |data: &AppState, _env| {
+            match data.get_key_bindings().is_valid(action) {
+                true => String::new(),
+                false => "Not a key".to_string(),
+            }
+        }))
+}
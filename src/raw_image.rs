@@ -0,0 +1,100 @@
+use std::error::Error;
+use std::path::Path;
+
+use image::{DynamicImage, ImageFormat, RgbImage};
+
+use crate::image_loader::DecodedImage;
+
+pub const RAW_EXTENSIONS: [&str; 7] = ["cr2", "cr3", "nef", "arw", "dng", "raf", "orf"];
+
+// Fujifilm files point straight at their preview from a fixed header
+const RAF_MAGIC: &[u8] = b"FUJIFILMCCD-RAW";
+const RAF_JPEG_OFFSET_POSITION: usize = 84;
+
+pub fn is_raw_path(path: &Path) -> bool {
+    path.extension()
+        .map(|extension| extension.to_ascii_lowercase())
+        .map_or(false, |extension| {
+            RAW_EXTENSIONS
+                .iter()
+                .any(|raw_extension| extension == *raw_extension)
+        })
+}
+
+/// Extracts the largest JPEG preview embedded in a camera RAW file. Every common RAW container
+/// stores one at or near full size, so this is much faster than developing the sensor data.
+pub fn decode_raw_preview(data: &[u8]) -> Result<DecodedImage, Box<dyn Error>> {
+    let jpeg_data = if data.starts_with(RAF_MAGIC) && data.len() >= RAF_JPEG_OFFSET_POSITION + 8 {
+        let read_u32 = |offset: usize| {
+            u32::from_be_bytes([
+                data[offset],
+                data[offset + 1],
+                data[offset + 2],
+                data[offset + 3],
+            ]) as usize
+        };
+        let offset = read_u32(RAF_JPEG_OFFSET_POSITION);
+        let length = read_u32(RAF_JPEG_OFFSET_POSITION + 4);
+        data.get(offset..offset + length)
+    } else {
+        find_largest_jpeg(data)
+    }
+    .ok_or("No embedded preview found")?;
+
+    let image = image::load_from_memory_with_format(jpeg_data, ImageFormat::Jpeg)?;
+    let mut decoded_image = DecodedImage::from_image(image);
+    decoded_image.raw_preview = true;
+    Ok(decoded_image)
+}
+
+/// Demosaics the sensor data with the camera's as-shot white balance
+pub fn develop_raw(path: &Path) -> Result<DecodedImage, Box<dyn Error>> {
+    let developed = imagepipe::simple_decode_8bit(path, 0, 0)?;
+    let image = RgbImage::from_raw(
+        developed.width as u32,
+        developed.height as u32,
+        developed.data,
+    )
+    .ok_or("Developed image has an unexpected size")?;
+    Ok(DecodedImage::from_image(DynamicImage::ImageRgb8(image)))
+}
+
+/// Scans for JPEG streams and returns the one with the largest frame. Lossless JPEG, which some
+/// formats use for the sensor data itself, is skipped since the image crate can't decode it.
+fn find_largest_jpeg(data: &[u8]) -> Option<&[u8]> {
+    let mut largest: Option<(usize, usize)> = None;
+    let mut position = 0;
+    while position + 3 < data.len() {
+        if data[position] == 0xFF && data[position + 1] == 0xD8 && data[position + 2] == 0xFF {
+            if let Some(area) = read_jpeg_frame_area(&data[position..]) {
+                if largest.map_or(true, |(_, largest_area)| area > largest_area) {
+                    largest = Some((position, area));
+                }
+            }
+        }
+        position += 1;
+    }
+    largest.map(|(offset, _)| &data[offset..])
+}
+
+fn read_jpeg_frame_area(jpeg: &[u8]) -> Option<usize> {
+    let mut offset = 2;
+    while offset + 9 < jpeg.len() && jpeg[offset] == 0xFF {
+        let marker = jpeg[offset + 1];
+        match marker {
+            // Baseline, extended and progressive frames
+            0xC0 | 0xC1 | 0xC2 => {
+                let height = u16::from_be_bytes([jpeg[offset + 5], jpeg[offset + 6]]) as usize;
+                let width = u16::from_be_bytes([jpeg[offset + 7], jpeg[offset + 8]]) as usize;
+                return Some(width * height);
+            }
+            // Any other frame type, or image data before a frame header
+            0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF | 0xD9 | 0xDA => return None,
+            _ => {
+                let length = u16::from_be_bytes([jpeg[offset + 2], jpeg[offset + 3]]) as usize;
+                offset += 2 + length;
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,177 @@
+use druid::kurbo::RoundedRect;
+use druid::piet::{Text, TextLayout, TextLayoutBuilder};
+use druid::widget::prelude::*;
+use druid::{Color, FontFamily, FontWeight, MouseButton, Point, Rect};
+
+use crate::app_state::AppState;
+
+const HEADER_HEIGHT: f64 = 32.;
+const ROW_HEIGHT: f64 = 28.;
+const MAX_ROWS: usize = 5;
+
+/// Recently opened images and folders, listed below the "Open file" button while nothing is open
+pub struct RecentWidget {
+    width: f64,
+    hot_row: Option<usize>,
+}
+
+impl RecentWidget {
+    pub fn new(width: f64) -> Self {
+        Self {
+            width,
+            hot_row: None,
+        }
+    }
+
+    /// The area in use, which shrinks with the number of entries
+    pub fn get_size(&self, data: &AppState) -> Size {
+        let row_count = data.get_recent_paths().len().min(MAX_ROWS);
+        match row_count {
+            0 => Size::ZERO,
+            _ => Size::new(
+                self.width,
+                HEADER_HEIGHT + row_count as f64 * ROW_HEIGHT + 8.,
+            ),
+        }
+    }
+
+    fn get_row_at(&self, position: Point, data: &AppState) -> Option<usize> {
+        if position.x < 0. || position.x > self.width || position.y < HEADER_HEIGHT {
+            return None;
+        }
+        let row = ((position.y - HEADER_HEIGHT) / ROW_HEIGHT) as usize;
+        (row < data.get_recent_paths().len().min(MAX_ROWS)).then_some(row)
+    }
+}
+
+impl Widget<AppState> for RecentWidget {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut AppState, _env: &Env) {
+        if let Event::MouseMove(m) = event {
+            let hot_row = self.get_row_at(m.pos, data);
+            if hot_row != self.hot_row {
+                self.hot_row = hot_row;
+                ctx.request_paint();
+            }
+        } else if let Event::MouseUp(m) = event {
+            if m.button == MouseButton::Left {
+                if let Some(row) = self.get_row_at(m.pos, data) {
+                    let path = data.get_recent_paths()[row].clone();
+                    self.hot_row = None;
+                    data.open_recent_path(&path);
+                    ctx.set_handled();
+                }
+            }
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        _data: &AppState,
+        _env: &Env,
+    ) {
+        if let LifeCycle::HotChanged(false) = event {
+            self.hot_row = None;
+            ctx.request_paint();
+        }
+    }
+
+    fn update(&mut self, _ctx: &mut UpdateCtx, _old_data: &AppState, _data: &AppState, _env: &Env) {
+    }
+
+    // Laid out at full size, so adding entries doesn't need a new layout pass
+    fn layout(
+        &mut self,
+        _layout_ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        _data: &AppState,
+        _env: &Env,
+    ) -> Size {
+        bc.constrain(Size::new(
+            self.width,
+            HEADER_HEIGHT + MAX_ROWS as f64 * ROW_HEIGHT + 8.,
+        ))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &AppState, _env: &Env) {
+        let size = self.get_size(data);
+        if size == Size::ZERO {
+            return;
+        }
+
+        let (text_color, detail_color, fill_color, hot_color) = if data.dark_theme_enabled {
+            (
+                Color::rgb8(255, 255, 255),
+                Color::rgb8(180, 180, 180),
+                Color::rgba(0.2, 0.2, 0.2, 0.5),
+                Color::rgba(1., 1., 1., 0.1),
+            )
+        } else {
+            (
+                Color::rgb8(0, 0, 0),
+                Color::rgb8(90, 90, 90),
+                Color::rgba(1., 1., 1., 0.5),
+                Color::rgba(0., 0., 0., 0.08),
+            )
+        };
+        let rect = size.to_rect();
+        let rect_rounded = RoundedRect::from_rect(rect, 10.);
+
+        ctx.with_save(|ctx| {
+            ctx.clip(rect_rounded);
+            ctx.fill(rect, &fill_color);
+
+            let header = ctx
+                .text()
+                .new_text_layout("Recent")
+                .font(FontFamily::SYSTEM_UI, 14.)
+                .default_attribute(FontWeight::BOLD)
+                .text_color(text_color.clone())
+                .build()
+                .unwrap();
+            ctx.draw_text(
+                &header,
+                Point::new(12., (HEADER_HEIGHT - header.size().height) / 2.),
+            );
+
+            for (row, path) in data.get_recent_paths().iter().take(MAX_ROWS).enumerate() {
+                let row_rect = Rect::from_origin_size(
+                    Point::new(0., HEADER_HEIGHT + row as f64 * ROW_HEIGHT),
+                    Size::new(self.width, ROW_HEIGHT),
+                );
+                if self.hot_row == Some(row) {
+                    ctx.fill(row_rect, &hot_color);
+                }
+
+                let name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_else(|| path.to_string_lossy().to_string());
+                let name_layout = ctx
+                    .text()
+                    .new_text_layout(name)
+                    .font(FontFamily::SYSTEM_UI, 13.)
+                    .text_color(text_color.clone())
+                    .build()
+                    .unwrap();
+                let text_y = row_rect.y0 + (ROW_HEIGHT - name_layout.size().height) / 2.;
+                ctx.draw_text(&name_layout, Point::new(12., text_y));
+
+                // The folder it's in, cut off at the edge if it doesn't fit
+                if let Some(parent) = path.parent() {
+                    let parent_layout = ctx
+                        .text()
+                        .new_text_layout(parent.to_string_lossy().to_string())
+                        .font(FontFamily::SYSTEM_UI, 11.)
+                        .text_color(detail_color.clone())
+                        .build()
+                        .unwrap();
+                    let parent_x = 24. + name_layout.size().width;
+                    let parent_y = row_rect.y0 + (ROW_HEIGHT - parent_layout.size().height) / 2.;
+                    ctx.draw_text(&parent_layout, Point::new(parent_x, parent_y));
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,210 @@
+//! A local socket for driving the viewer from scripts. Each line sent is one command, either as
+//! text ("open /path/to/image.png", "rotate left") or as JSON ({"command": "open", "path": ...}),
+//! and is answered with one line of JSON. A connection that sends "subscribe" then receives a
+//! line of JSON for every event, such as {"event": "image_changed", "path": ...}.
+
+use std::path::PathBuf;
+
+use druid::ExtEventSink;
+
+#[cfg(unix)]
+mod socket {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::PathBuf;
+    use std::sync::mpsc::{self, SyncSender};
+    use std::sync::Mutex;
+    use std::thread;
+    use std::time::Instant;
+
+    use druid::commands::OPEN_FILE;
+    use druid::{ExtEventSink, FileInfo, Target};
+    use serde_json::{json, Value};
+
+    use crate::commands::{
+        DELETE_IMAGE, NEXT_IMAGE, PREV_IMAGE, REALSIZE_IMAGE, RECENTER_IMAGE, ROTATE_LEFT,
+        ROTATE_RIGHT, ZOOM_IMAGE,
+    };
+
+    static SOCKET_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+    static SUBSCRIBERS: Mutex<Vec<SyncSender<String>>> = Mutex::new(Vec::new());
+    static CURRENT_PATH: Mutex<Option<String>> = Mutex::new(None);
+
+    // Lines waiting to be written to a client. A subscriber that falls this far behind is
+    // dropped, rather than holding up the UI thread or piling up events.
+    const CLIENT_QUEUE_LIMIT: usize = 64;
+
+    enum Request {
+        Next,
+        Prev,
+        Open(PathBuf),
+        RotateLeft,
+        RotateRight,
+        Zoom,
+        Recenter,
+        RealSize,
+        Delete,
+        GetCurrentPath,
+        Subscribe,
+    }
+
+    pub fn listen(socket_path: PathBuf, event_sink: ExtEventSink) {
+        // Only replace a socket left behind, never some other file
+        let is_socket = std::fs::symlink_metadata(&socket_path)
+            .map_or(false, |metadata| metadata.file_type().is_socket());
+        if is_socket {
+            let _result = std::fs::remove_file(&socket_path);
+        }
+        let listener = match UnixListener::bind(&socket_path) {
+            Ok(listener) => listener,
+            Err(error) => {
+                println!("Failed to open control socket: {}", error);
+                return;
+            }
+        };
+        *SOCKET_PATH.lock().unwrap() = Some(socket_path);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let event_sink = event_sink.clone();
+                thread::spawn(move || handle_client(stream, event_sink));
+            }
+        });
+    }
+
+    pub fn stop_listening() {
+        if let Some(socket_path) = SOCKET_PATH.lock().unwrap().take() {
+            let _result = std::fs::remove_file(socket_path);
+        }
+    }
+
+    pub fn publish_event(event: &str, path: &str) {
+        *CURRENT_PATH.lock().unwrap() = Some(path.to_string());
+        let message = json!({ "event": event, "path": path }).to_string();
+        let mut subscribers = SUBSCRIBERS.lock().unwrap();
+        // Never blocks, and a subscriber that's gone or too far behind is dropped
+        subscribers.retain(|subscriber| subscriber.try_send(message.clone()).is_ok());
+    }
+
+    fn handle_client(stream: UnixStream, event_sink: ExtEventSink) {
+        let reader = match stream.try_clone() {
+            Ok(reader) => BufReader::new(reader),
+            Err(_) => return,
+        };
+        // Responses and events are written by one thread, so their lines never interleave
+        let (sender, receiver) = mpsc::sync_channel::<String>(CLIENT_QUEUE_LIMIT);
+        let mut writer = stream;
+        thread::spawn(move || {
+            for line in receiver {
+                if writeln!(writer, "{}", line).is_err() {
+                    break;
+                }
+            }
+        });
+        for line in reader.lines().map_while(Result::ok) {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let response = match parse_request(line) {
+                Ok(Request::GetCurrentPath) => {
+                    json!({ "ok": true, "path": *CURRENT_PATH.lock().unwrap() })
+                }
+                Ok(Request::Subscribe) => {
+                    SUBSCRIBERS.lock().unwrap().push(sender.clone());
+                    json!({ "ok": true })
+                }
+                Ok(request) => match submit_request(request, &event_sink) {
+                    Ok(()) => json!({ "ok": true }),
+                    Err(error) => json!({ "ok": false, "error": error }),
+                },
+                Err(error) => json!({ "ok": false, "error": error }),
+            };
+            if sender.send(response.to_string()).is_err() {
+                break;
+            }
+        }
+    }
+
+    fn parse_request(line: &str) -> Result<Request, String> {
+        if line.starts_with('{') {
+            let message: Value =
+                serde_json::from_str(line).map_err(|error| format!("Invalid JSON: {}", error))?;
+            let command = message["command"]
+                .as_str()
+                .ok_or("Missing \"command\" field")?;
+            let argument = message["path"]
+                .as_str()
+                .or_else(|| message["direction"].as_str());
+            parse_command(command, argument)
+        } else {
+            match line.split_once(' ') {
+                Some((command, argument)) => parse_command(command, Some(argument.trim())),
+                None => parse_command(line, None),
+            }
+        }
+    }
+
+    fn parse_command(command: &str, argument: Option<&str>) -> Result<Request, String> {
+        match (command, argument) {
+            ("next", None) => Ok(Request::Next),
+            ("prev", None) => Ok(Request::Prev),
+            ("open", Some(path)) => Ok(Request::Open(PathBuf::from(path))),
+            ("rotate", Some("left")) => Ok(Request::RotateLeft),
+            ("rotate", Some("right")) => Ok(Request::RotateRight),
+            ("zoom", None) => Ok(Request::Zoom),
+            ("recenter", None) => Ok(Request::Recenter),
+            ("realsize", None) => Ok(Request::RealSize),
+            ("delete", None) => Ok(Request::Delete),
+            ("get-current-path", None) => Ok(Request::GetCurrentPath),
+            ("subscribe", None) => Ok(Request::Subscribe),
+            _ => Err(format!("Unknown command: {}", command)),
+        }
+    }
+
+    /// Sends the same command the matching button or key would
+    fn submit_request(request: Request, event_sink: &ExtEventSink) -> Result<(), String> {
+        let now = Instant::now();
+        match request {
+            Request::Next => event_sink.submit_command(NEXT_IMAGE, now, Target::Auto),
+            Request::Prev => event_sink.submit_command(PREV_IMAGE, now, Target::Auto),
+            Request::Open(path) => {
+                let file_info = FileInfo { path, format: None };
+                event_sink.submit_command(OPEN_FILE, file_info, Target::Auto)
+            }
+            Request::RotateLeft => event_sink.submit_command(ROTATE_LEFT, now, Target::Auto),
+            Request::RotateRight => event_sink.submit_command(ROTATE_RIGHT, now, Target::Auto),
+            Request::Zoom => event_sink.submit_command(ZOOM_IMAGE, now, Target::Auto),
+            Request::Recenter => event_sink.submit_command(RECENTER_IMAGE, now, Target::Auto),
+            Request::RealSize => event_sink.submit_command(REALSIZE_IMAGE, now, Target::Auto),
+            Request::Delete => event_sink.submit_command(DELETE_IMAGE, now, Target::Auto),
+            Request::GetCurrentPath | Request::Subscribe => Ok(()),
+        }
+        .map_err(|error| error.to_string())
+    }
+}
+
+/// Starts accepting commands on a socket at the given path
+pub fn listen(socket_path: PathBuf, event_sink: ExtEventSink) {
+    #[cfg(unix)]
+    socket::listen(socket_path, event_sink);
+    #[cfg(not(unix))]
+    {
+        let _unused = (socket_path, event_sink);
+        println!("Remote control needs Unix domain sockets, which aren't available here");
+    }
+}
+
+pub fn stop_listening() {
+    #[cfg(unix)]
+    socket::stop_listening();
+}
+
+/// Tells subscribers about something that happened, and remembers the path for
+/// "get-current-path"
+pub fn publish_event(event: &str, path: &str) {
+    #[cfg(unix)]
+    socket::publish_event(event, path);
+    #[cfg(not(unix))]
+    let _unused = (event, path);
+}
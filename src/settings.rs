@@ -0,0 +1,515 @@
+//! Typed settings, stored as TOML in the user's config folder
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+use preferences::{AppInfo, Preferences, PreferencesMap};
+use serde::de::DeserializeOwned;
+use serde::{Serialize, Serializer};
+use toml::{Table, Value};
+
+use crate::types::{CanvasBackground, SortMode, ThemeMode, ZoomMode};
+
+/// Bumped whenever a setting is renamed or changes meaning, with a migration step in
+/// `upgrade_table` for the previous version
+const SETTINGS_VERSION: i64 = 1;
+
+/// Ranges offered in the preferences window, which values from the file are clamped to
+pub const PREFETCH_COUNT_RANGE: RangeInclusive<usize> = 0..=10;
+pub const CACHE_MEMORY_LIMIT_MB_RANGE: RangeInclusive<usize> = 64..=16384;
+
+// Where settings were kept before this file existed
+const LEGACY_APP_SIG: AppInfo = AppInfo {
+    name: env!("CARGO_PKG_NAME"),
+    author: env!("CARGO_PKG_AUTHORS"),
+};
+
+#[derive(Clone, Serialize)]
+pub struct Settings {
+    pub version: i64,
+    pub blur_enabled: bool,
+    pub image_filtering_enabled: bool,
+    pub pixel_grid_enabled: bool,
+    pub histogram_enabled: bool,
+    pub canvas_background: CanvasBackground,
+    pub theme: ThemeMode,
+    pub sort_mode: SortMode,
+    pub zoom_mode: ZoomMode,
+    pub recursive_browsing_enabled: bool,
+    #[serde(serialize_with = "serialize_depth_limit")]
+    pub folder_depth_limit: Option<usize>,
+    pub skip_hidden_folders: bool,
+    pub single_instance_enabled: bool,
+    /// Seconds between images in a slideshow
+    pub slideshow_interval: f64,
+    /// How many of the following images are decoded ahead of time
+    pub prefetch_count: usize,
+    pub cache_memory_limit_mb: usize,
+    pub wrap_around_enabled: bool,
+    pub confirm_delete: bool,
+    /// Reopen the last image when started without any files
+    pub restore_session: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_image: Option<String>,
+    /// Width and height of the window when it isn't maximized
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window_size: Option<[f64; 2]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window_position: Option<[f64; 2]>,
+    pub window_maximized: bool,
+    /// Images and folders opened recently, newest first
+    pub recent_paths: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub monitor_profile_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proofing_profile_path: Option<String>,
+    /// Action names to keys. Kept last, as TOML tables have to follow plain values.
+    pub key_bindings: BTreeMap<String, String>,
+    /// The file couldn't be read or set aside, so saving would lose what's in it
+    #[serde(skip)]
+    pub save_disabled: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            blur_enabled: true,
+            image_filtering_enabled: false,
+            pixel_grid_enabled: false,
+            histogram_enabled: false,
+            canvas_background: CanvasBackground::Checkerboard,
+            theme: ThemeMode::System,
+            sort_mode: SortMode::Name,
+            zoom_mode: ZoomMode::Fit,
+            recursive_browsing_enabled: false,
+            folder_depth_limit: Some(3),
+            skip_hidden_folders: true,
+            single_instance_enabled: false,
+            slideshow_interval: 5.,
+            prefetch_count: 2,
+            cache_memory_limit_mb: 512,
+            wrap_around_enabled: true,
+            confirm_delete: true,
+            restore_session: true,
+            last_image: None,
+            window_size: None,
+            window_position: None,
+            window_maximized: false,
+            recent_paths: Vec::new(),
+            monitor_profile_path: None,
+            proofing_profile_path: None,
+            key_bindings: BTreeMap::new(),
+            save_disabled: false,
+        }
+    }
+}
+
+impl Settings {
+    pub fn get_path() -> Option<PathBuf> {
+        Some(
+            dirs::config_dir()?
+                .join(env!("CARGO_PKG_NAME"))
+                .join("settings.toml"),
+        )
+    }
+
+    /// Reads the settings file, or the old preference maps if there's no file yet. Anything
+    /// that can't be used is reported and left at its default, without losing the rest.
+    pub fn load() -> (Self, Vec<String>) {
+        let mut errors = Vec::new();
+        let path = match Self::get_path() {
+            Some(path) => path,
+            None => {
+                errors.push("No config folder was found".to_string());
+                return (Self::default(), errors);
+            }
+        };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return (Self::from_legacy_preferences(), errors);
+            }
+            Err(error) => {
+                errors.push(format!(
+                    "Failed to read {}, so settings won't be saved: {}",
+                    path.display(),
+                    error
+                ));
+                return (Self::unsaved_default(), errors);
+            }
+        };
+        let mut table = match text.parse::<Table>() {
+            Ok(table) => table,
+            Err(error) => {
+                errors.push(format!("{} isn't valid TOML: {}", path.display(), error));
+                // Set aside, so the defaults saved on exit don't replace it
+                let backup_path = path.with_extension("toml.bak");
+                return match std::fs::rename(&path, &backup_path) {
+                    Ok(()) => {
+                        errors.push(format!("It was kept as {}", backup_path.display()));
+                        (Self::default(), errors)
+                    }
+                    Err(error) => {
+                        errors.push(format!(
+                            "It couldn't be kept as {}, so settings won't be saved: {}",
+                            backup_path.display(),
+                            error
+                        ));
+                        (Self::unsaved_default(), errors)
+                    }
+                };
+            }
+        };
+
+        match table.get("version").and_then(Value::as_integer) {
+            Some(version) if version > SETTINGS_VERSION => errors.push(format!(
+                "Settings are from a newer version ({}), so some may be ignored",
+                version
+            )),
+            Some(version) => upgrade_table(&mut table, version),
+            None => errors.push("Settings have no version, reading them as current".to_string()),
+        }
+        (Self::from_table(&table, &mut errors), errors)
+    }
+
+    fn unsaved_default() -> Self {
+        Self {
+            save_disabled: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let path = Self::get_path().ok_or("No config folder was found")?;
+        if let Some(folder) = path.parent() {
+            std::fs::create_dir_all(folder)?;
+        }
+        std::fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Copies one setting, named as in the file, from another set of settings
+    pub fn copy_setting(&mut self, other: &Settings, key: &str) {
+        match key {
+            "blur_enabled" => self.blur_enabled = other.blur_enabled,
+            "image_filtering_enabled" => {
+                self.image_filtering_enabled = other.image_filtering_enabled
+            }
+            "recursive_browsing_enabled" => {
+                self.recursive_browsing_enabled = other.recursive_browsing_enabled
+            }
+            "sort_mode" => self.sort_mode = other.sort_mode,
+            "zoom_mode" => self.zoom_mode = other.zoom_mode,
+            _ => println!("Setting {} can't be copied", key),
+        }
+    }
+
+    fn from_table(table: &Table, errors: &mut Vec<String>) -> Self {
+        let mut settings = Self::default();
+        for (key, value) in table {
+            let result = match key.as_str() {
+                "version" => Ok(()),
+                "blur_enabled" => read_value(value, &mut settings.blur_enabled),
+                "image_filtering_enabled" => {
+                    read_value(value, &mut settings.image_filtering_enabled)
+                }
+                "pixel_grid_enabled" => read_value(value, &mut settings.pixel_grid_enabled),
+                "histogram_enabled" => read_value(value, &mut settings.histogram_enabled),
+                "canvas_background" => read_value(value, &mut settings.canvas_background),
+                "theme" => read_value(value, &mut settings.theme),
+                "sort_mode" => read_value(value, &mut settings.sort_mode),
+                "zoom_mode" => read_value(value, &mut settings.zoom_mode),
+                "recursive_browsing_enabled" => {
+                    read_value(value, &mut settings.recursive_browsing_enabled)
+                }
+                "folder_depth_limit" => match value {
+                    Value::String(limit) if limit == "unlimited" => {
+                        settings.folder_depth_limit = None;
+                        Ok(())
+                    }
+                    Value::Integer(limit) if *limit > 0 => {
+                        settings.folder_depth_limit = Some(*limit as usize);
+                        Ok(())
+                    }
+                    _ => Err("expected a positive number or \"unlimited\"".to_string()),
+                },
+                "skip_hidden_folders" => read_value(value, &mut settings.skip_hidden_folders),
+                "single_instance_enabled" => {
+                    read_value(value, &mut settings.single_instance_enabled)
+                }
+                "slideshow_interval" => {
+                    match value
+                        .as_float()
+                        .or_else(|| value.as_integer().map(|i| i as f64))
+                    {
+                        Some(interval) if interval > 0. => {
+                            settings.slideshow_interval = interval;
+                            Ok(())
+                        }
+                        _ => Err("expected a positive number of seconds".to_string()),
+                    }
+                }
+                "prefetch_count" => {
+                    read_clamped(value, PREFETCH_COUNT_RANGE, &mut settings.prefetch_count)
+                }
+                "cache_memory_limit_mb" => read_clamped(
+                    value,
+                    CACHE_MEMORY_LIMIT_MB_RANGE,
+                    &mut settings.cache_memory_limit_mb,
+                ),
+                "wrap_around_enabled" => read_value(value, &mut settings.wrap_around_enabled),
+                "confirm_delete" => read_value(value, &mut settings.confirm_delete),
+                "restore_session" => read_value(value, &mut settings.restore_session),
+                "last_image" => read_value(value, &mut settings.last_image),
+                "window_size" => read_value(value, &mut settings.window_size),
+                "window_position" => read_value(value, &mut settings.window_position),
+                "window_maximized" => read_value(value, &mut settings.window_maximized),
+                "recent_paths" => read_value(value, &mut settings.recent_paths),
+                "key_bindings" => read_value(value, &mut settings.key_bindings),
+                "monitor_profile_path" => read_value(value, &mut settings.monitor_profile_path),
+                "proofing_profile_path" => read_value(value, &mut settings.proofing_profile_path),
+                _ => Err("unknown setting".to_string()),
+            };
+            if let Err(error) = result {
+                errors.push(format!("{}: {}", key, error));
+            }
+        }
+        settings
+    }
+
+    /// Builds settings from the boolean and string maps used before the settings file
+    fn from_legacy_preferences() -> Self {
+        let flags = HashMap::<String, bool>::load(&LEGACY_APP_SIG, "preferences");
+        let display_preferences =
+            PreferencesMap::<String>::load(&LEGACY_APP_SIG, "display_preferences");
+        Self::from_legacy_maps(flags.ok().as_ref(), display_preferences.ok().as_ref())
+    }
+
+    fn from_legacy_maps(
+        flags: Option<&HashMap<String, bool>>,
+        display_preferences: Option<&PreferencesMap<String>>,
+    ) -> Self {
+        let mut settings = Self::default();
+        if let Some(preferences) = flags {
+            let flags = [
+                ("blur_enabled", &mut settings.blur_enabled),
+                (
+                    "image_filtering_enabled",
+                    &mut settings.image_filtering_enabled,
+                ),
+                ("pixel_grid_enabled", &mut settings.pixel_grid_enabled),
+                ("histogram_enabled", &mut settings.histogram_enabled),
+                (
+                    "recursive_browsing_enabled",
+                    &mut settings.recursive_browsing_enabled,
+                ),
+                ("skip_hidden_folders", &mut settings.skip_hidden_folders),
+                (
+                    "single_instance_enabled",
+                    &mut settings.single_instance_enabled,
+                ),
+            ];
+            for (key, flag) in flags {
+                if let Some(value) = preferences.get(key) {
+                    *flag = *value;
+                }
+            }
+        }
+        if let Some(preferences) = display_preferences {
+            if let Some(background) = preferences
+                .get("canvas_background")
+                .and_then(|b| CanvasBackground::from_pref_string(b))
+            {
+                settings.canvas_background = background;
+            }
+            if let Some(sort_mode) = preferences
+                .get("sort_mode")
+                .and_then(|s| SortMode::from_pref_string(s))
+            {
+                settings.sort_mode = sort_mode;
+            }
+            if let Some(zoom_mode) = preferences
+                .get("zoom_mode")
+                .and_then(|z| ZoomMode::from_pref_string(z))
+            {
+                settings.zoom_mode = zoom_mode;
+            }
+            if let Some(depth_limit) = preferences.get("folder_depth_limit") {
+                // Anything that isn't a number meant no limit
+                settings.folder_depth_limit = depth_limit.parse().ok();
+            }
+            settings.monitor_profile_path = preferences.get("monitor_profile_path").cloned();
+            settings.proofing_profile_path = preferences.get("proofing_profile_path").cloned();
+        }
+        settings
+    }
+}
+
+/// Rewrites a table from an older schema version into the current one. Version 1 is the first,
+/// so there's nothing to do yet.
+fn upgrade_table(table: &mut Table, version: i64) {
+    if version < SETTINGS_VERSION {
+        table.insert("version".to_string(), Value::Integer(SETTINGS_VERSION));
+    }
+}
+
+fn read_value<T: DeserializeOwned>(value: &Value, target: &mut T) -> Result<(), String> {
+    *target = value
+        .clone()
+        .try_into()
+        .map_err(|error| error.to_string())?;
+    Ok(())
+}
+
+/// Reads a number, clamping it into the range and reporting it if it was outside
+fn read_clamped(
+    value: &Value,
+    range: RangeInclusive<usize>,
+    target: &mut usize,
+) -> Result<(), String> {
+    let mut number = *target;
+    read_value(value, &mut number)?;
+    *target = number.clamp(*range.start(), *range.end());
+    if range.contains(&number) {
+        Ok(())
+    } else {
+        Err(format!(
+            "expected {} to {}, using {}",
+            range.start(),
+            range.end(),
+            target
+        ))
+    }
+}
+
+fn serialize_depth_limit<S: Serializer>(
+    depth_limit: &Option<usize>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match depth_limit {
+        Some(depth_limit) => serializer.serialize_u64(*depth_limit as u64),
+        None => serializer.serialize_str("unlimited"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> (Settings, Vec<String>) {
+        let mut errors = Vec::new();
+        let settings = Settings::from_table(&text.parse::<Table>().unwrap(), &mut errors);
+        (settings, errors)
+    }
+
+    #[test]
+    fn table_values() {
+        let (settings, errors) = parse(
+            r#"
+            version = 1
+            blur_enabled = false
+            sort_mode = "natural"
+            zoom_mode = "1:1"
+            folder_depth_limit = "unlimited"
+            slideshow_interval = 2
+            window_size = [800.0, 600.0]
+            recent_paths = ["/a.png", "/b"]
+
+            [key_bindings]
+            next_image = "n"
+            "#,
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert!(!settings.blur_enabled);
+        assert_eq!(settings.sort_mode, SortMode::Natural);
+        assert_eq!(settings.zoom_mode, ZoomMode::RealSize);
+        assert_eq!(settings.folder_depth_limit, None);
+        assert_eq!(settings.slideshow_interval, 2.);
+        assert_eq!(settings.window_size, Some([800., 600.]));
+        assert_eq!(settings.recent_paths, ["/a.png", "/b"]);
+        assert_eq!(settings.key_bindings["next_image"], "n");
+    }
+
+    #[test]
+    fn table_errors_keep_other_values() {
+        let (settings, errors) = parse(
+            r#"
+            blur_enabled = "yes"
+            sort_mode = "random"
+            folder_depth_limit = 0
+            slideshow_interval = -1.0
+            unknown_option = true
+            histogram_enabled = true
+            "#,
+        );
+        assert_eq!(errors.len(), 5, "{:?}", errors);
+        assert!(errors
+            .iter()
+            .any(|error| error.starts_with("unknown_option:")));
+        assert!(settings.blur_enabled);
+        assert_eq!(settings.sort_mode, SortMode::Name);
+        assert_eq!(settings.folder_depth_limit, Some(3));
+        assert_eq!(settings.slideshow_interval, 5.);
+        assert!(settings.histogram_enabled);
+    }
+
+    #[test]
+    fn table_values_out_of_range() {
+        let (settings, errors) = parse(
+            r#"
+            prefetch_count = 1000
+            cache_memory_limit_mb = 18446744073709
+            "#,
+        );
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert_eq!(settings.prefetch_count, *PREFETCH_COUNT_RANGE.end());
+        assert_eq!(
+            settings.cache_memory_limit_mb,
+            *CACHE_MEMORY_LIMIT_MB_RANGE.end()
+        );
+    }
+
+    #[test]
+    fn legacy_preferences() {
+        let flags = HashMap::from([
+            ("blur_enabled".to_string(), false),
+            ("histogram_enabled".to_string(), true),
+        ]);
+        let display_preferences = PreferencesMap::from([
+            ("canvas_background".to_string(), "#808080".to_string()),
+            ("sort_mode".to_string(), "modified".to_string()),
+            ("zoom_mode".to_string(), "bogus".to_string()),
+            ("folder_depth_limit".to_string(), "none".to_string()),
+            (
+                "monitor_profile_path".to_string(),
+                "/profile.icc".to_string(),
+            ),
+        ]);
+        let settings = Settings::from_legacy_maps(Some(&flags), Some(&display_preferences));
+        assert!(!settings.blur_enabled);
+        assert!(settings.histogram_enabled);
+        assert!(!settings.image_filtering_enabled);
+        assert_eq!(
+            settings.canvas_background,
+            CanvasBackground::Solid(druid::Color::rgb8(128, 128, 128))
+        );
+        assert_eq!(settings.sort_mode, SortMode::Modified);
+        assert_eq!(settings.zoom_mode, ZoomMode::Fit);
+        assert_eq!(settings.folder_depth_limit, None);
+        assert_eq!(
+            settings.monitor_profile_path.as_deref(),
+            Some("/profile.icc")
+        );
+    }
+
+    #[test]
+    fn no_legacy_preferences() {
+        let settings = Settings::from_legacy_maps(None, None);
+        assert!(settings.blur_enabled);
+        assert_eq!(settings.folder_depth_limit, Some(3));
+        assert!(!settings.save_disabled);
+    }
+}
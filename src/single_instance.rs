@@ -0,0 +1,213 @@
+//! Lets a second launch hand its files to the window that's already open, over a Unix socket
+
+#[cfg(unix)]
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use druid::ExtEventSink;
+
+use crate::playlist::LaunchPaths;
+
+// Only the process that owns the socket may remove it
+#[cfg(unix)]
+static LISTENING: AtomicBool = AtomicBool::new(false);
+
+/// A launch handed over by another process, with its paths already made absolute
+pub struct ForwardedLaunch {
+    pub option_args: Vec<String>,
+    pub launch_paths: LaunchPaths,
+}
+
+#[cfg(unix)]
+mod socket {
+    use std::fs::DirBuilder;
+    use std::io::{ErrorKind, Read, Write};
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+    use std::thread;
+    use std::time::Duration;
+
+    use druid::{ExtEventSink, SingleUse, Target};
+    use serde_json::{json, Value};
+
+    use super::ForwardedLaunch;
+    use crate::commands::FORWARDED_LAUNCH;
+    use crate::playlist::LaunchPaths;
+
+    /// How long a launching process gets to send its message before it's dropped
+    const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn get_socket_path() -> Option<PathBuf> {
+        match std::env::var_os("XDG_RUNTIME_DIR") {
+            Some(runtime_dir) => Some(PathBuf::from(runtime_dir).join("foxfire.sock")),
+            None => get_private_temp_folder().map(|folder| folder.join("foxfire.sock")),
+        }
+    }
+
+    /// A folder in the shared temporary folder that only the current user can get into, so
+    /// nobody else can put a socket in its place
+    fn get_private_temp_folder() -> Option<PathBuf> {
+        let uid = unsafe { libc::getuid() };
+        let folder = std::env::temp_dir().join(format!("foxfire-{}", uid));
+        if let Err(error) = DirBuilder::new().mode(0o700).create(&folder) {
+            if error.kind() != ErrorKind::AlreadyExists {
+                println!("Failed to create {}: {}", folder.display(), error);
+                return None;
+            }
+        }
+        let metadata = std::fs::symlink_metadata(&folder).ok()?;
+        if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+            println!(
+                "Not sharing launches, as {} isn't a private folder",
+                folder.display()
+            );
+            return None;
+        }
+        Some(folder)
+    }
+
+    pub fn forward_launch(option_args: &[String], launch_paths: &LaunchPaths) -> bool {
+        let socket_path = match get_socket_path() {
+            Some(socket_path) => socket_path,
+            None => return false,
+        };
+        let mut stream = match UnixStream::connect(socket_path) {
+            Ok(stream) => stream,
+            Err(_) => return false,
+        };
+        // The running instance has its own working folder, so relative paths are resolved here
+        let current_dir = std::env::current_dir().unwrap_or_default();
+        let absolute = |path: &Path| current_dir.join(path).to_string_lossy().to_string();
+        let message = match launch_paths {
+            LaunchPaths::None => json!({ "options": option_args }),
+            LaunchPaths::Single(path) => json!({
+                "options": option_args,
+                "single": absolute(Path::new(path)),
+            }),
+            LaunchPaths::List(paths) => json!({
+                "options": option_args,
+                "list": paths.iter().map(|path| absolute(path)).collect::<Vec<_>>(),
+            }),
+        };
+        stream.write_all(message.to_string().as_bytes()).is_ok()
+    }
+
+    pub fn listen(event_sink: ExtEventSink) {
+        let socket_path = match get_socket_path() {
+            Some(socket_path) => socket_path,
+            None => return,
+        };
+        // Only a socket nobody answers on is left behind and safe to replace. One that answers
+        // belongs to an instance that started at the same time, which keeps it.
+        match UnixStream::connect(&socket_path) {
+            Ok(_stream) => {
+                println!("Another instance is already listening for launches");
+                return;
+            }
+            Err(error) if error.kind() == ErrorKind::ConnectionRefused => {
+                let _result = std::fs::remove_file(&socket_path);
+            }
+            Err(_) => {}
+        }
+        let listener = match UnixListener::bind(&socket_path) {
+            Ok(listener) => {
+                super::LISTENING.store(true, super::Ordering::SeqCst);
+                listener
+            }
+            Err(error) => {
+                println!("Failed to listen for other launches: {}", error);
+                return;
+            }
+        };
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // A client that never finishes its message only holds up its own thread
+                let event_sink = event_sink.clone();
+                thread::spawn(move || handle_launch(stream, event_sink));
+            }
+        });
+    }
+
+    fn handle_launch(mut stream: UnixStream, event_sink: ExtEventSink) {
+        let _result = stream.set_read_timeout(Some(READ_TIMEOUT));
+        let mut message = String::new();
+        // Another instance checking whether this one is alive sends nothing
+        if stream.read_to_string(&mut message).is_err() || message.is_empty() {
+            return;
+        }
+        match parse_message(&message) {
+            Some(forwarded_launch) => {
+                // The event sink is gone once the app is shutting down
+                if let Err(error) = event_sink.submit_command(
+                    FORWARDED_LAUNCH,
+                    SingleUse::new(forwarded_launch),
+                    Target::Auto,
+                ) {
+                    println!("Failed to send forwarded launch command: {}", error);
+                }
+            }
+            None => println!("Ignoring malformed message from another launch"),
+        }
+    }
+
+    pub fn stop_listening() {
+        if let Some(socket_path) = get_socket_path() {
+            let _result = std::fs::remove_file(socket_path);
+        }
+    }
+
+    fn parse_message(message: &str) -> Option<ForwardedLaunch> {
+        let message: Value = serde_json::from_str(message).ok()?;
+        let option_args = message["options"]
+            .as_array()?
+            .iter()
+            .filter_map(|arg| arg.as_str().map(str::to_string))
+            .collect();
+        let launch_paths = if let Some(path) = message["single"].as_str() {
+            LaunchPaths::Single(path.to_string())
+        } else if let Some(paths) = message["list"].as_array().filter(|paths| !paths.is_empty()) {
+            LaunchPaths::List(
+                paths
+                    .iter()
+                    .filter_map(|path| path.as_str().map(PathBuf::from))
+                    .collect(),
+            )
+        } else {
+            LaunchPaths::None
+        };
+        Some(ForwardedLaunch {
+            option_args,
+            launch_paths,
+        })
+    }
+}
+
+/// Hands the launch to a running instance. Returns false if there's none to take it.
+pub fn forward_launch(option_args: &[String], launch_paths: &LaunchPaths) -> bool {
+    #[cfg(unix)]
+    return socket::forward_launch(option_args, launch_paths);
+    #[cfg(not(unix))]
+    {
+        let _unused = (option_args, launch_paths);
+        false
+    }
+}
+
+/// Starts accepting launches from other processes
+pub fn listen(event_sink: ExtEventSink) {
+    #[cfg(unix)]
+    socket::listen(event_sink);
+    #[cfg(not(unix))]
+    drop(event_sink);
+}
+
+pub fn stop_listening() {
+    #[cfg(unix)]
+    if LISTENING.swap(false, Ordering::SeqCst) {
+        socket::stop_listening();
+    }
+}
+
+pub fn is_supported() -> bool {
+    cfg!(unix)
+}
@@ -0,0 +1,33 @@
+//! Follows the desktop switching between light and dark while the viewer is open
+
+use std::thread;
+use std::time::Duration;
+
+use druid::{ExtEventSink, Target};
+
+use crate::commands::SYSTEM_THEME_CHANGED;
+
+// Not every desktop announces theme changes, so the setting is checked every so often
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+pub fn is_dark() -> bool {
+    matches!(dark_light::detect(), dark_light::Mode::Dark)
+}
+
+/// Starts checking the theme in the background, starting from the one already known
+pub fn watch(mut dark: bool, event_sink: ExtEventSink) {
+    thread::spawn(move || loop {
+        thread::sleep(POLL_INTERVAL);
+        let is_now_dark = is_dark();
+        if is_now_dark != dark {
+            dark = is_now_dark;
+            // Fails once the app has quit
+            if event_sink
+                .submit_command(SYSTEM_THEME_CHANGED, dark, Target::Auto)
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+}
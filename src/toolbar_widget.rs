@@ -4,8 +4,10 @@ use crate::commands::{
     DELETE_IMAGE, NEXT_IMAGE, PREV_IMAGE, REALSIZE_IMAGE, RECENTER_IMAGE, ROTATE_LEFT,
     ROTATE_RIGHT, ZOOM_IMAGE,
 };
-use crate::types::DisplayState;
-use crate::TOGGLE_BLUR;
+use crate::single_instance;
+use crate::types::{CanvasBackground, DisplayState, SortMode, ZoomMode};
+use crate::{SHOW_PREFERENCES, TOGGLE_BLUR};
+use druid::commands::CLOSE_WINDOW;
 use druid::widget::prelude::*;
 use druid::widget::Svg;
 use druid::widget::SvgData;
@@ -302,6 +304,125 @@ fn generate_menu(data: &AppState) -> Menu<AppState> {
     const ABOUT_STR: &str = concat!("Foxfire v", env!("CARGO_PKG_VERSION"));
     let blur_state = data.blur_enabled();
     let filtering_state = data.image_filtering_enabled();
+    let pixel_grid_state = data.pixel_grid_enabled();
+
+    let mut tile_grid_menu = Menu::new(LocalizedString::new("Tile grid")).entry(
+        MenuItem::new(LocalizedString::new("None"))
+            .selected(data.tile_grid_size().is_none())
+            .on_activate(|_ctx, data: &mut AppState, _env| data.set_tile_grid_size(None)),
+    );
+    for size in [8, 16, 32, 64, 128] {
+        tile_grid_menu = tile_grid_menu.entry(
+            MenuItem::new(format!("{} × {}", size, size))
+                .selected(data.tile_grid_size() == Some(size))
+                .on_activate(move |_ctx, data: &mut AppState, _env| {
+                    data.set_tile_grid_size(Some(size))
+                }),
+        );
+    }
+
+    let zoom_choices = [
+        ("Fit to window", ZoomMode::Fit),
+        ("Actual size", ZoomMode::RealSize),
+    ];
+    let mut zoom_menu = Menu::new(LocalizedString::new("Open images at"));
+    for (label, zoom_mode) in zoom_choices {
+        zoom_menu = zoom_menu.entry(
+            MenuItem::new(LocalizedString::new(label))
+                .selected(data.zoom_mode() == zoom_mode)
+                .on_activate(move |_ctx, data: &mut AppState, _env| data.set_zoom_mode(zoom_mode)),
+        );
+    }
+
+    let background_choices = [
+        ("Follow system theme", CanvasBackground::Theme),
+        (
+            "Checkerboard behind transparency",
+            CanvasBackground::Checkerboard,
+        ),
+        ("Black", CanvasBackground::Solid(Color::BLACK)),
+        ("Gray", CanvasBackground::Solid(Color::rgb8(128, 128, 128))),
+        ("White", CanvasBackground::Solid(Color::WHITE)),
+    ];
+    let mut background_menu = Menu::new(LocalizedString::new("Background"));
+    for (label, background) in background_choices {
+        background_menu = background_menu.entry(
+            MenuItem::new(LocalizedString::new(label))
+                .selected(data.get_canvas_background() == &background)
+                .on_activate(move |_ctx, data: &mut AppState, _env| {
+                    data.set_canvas_background(background.clone())
+                }),
+        );
+    }
+
+    let color_management = data.get_color_management();
+    let has_monitor_profile = color_management.get_monitor_profile_path().is_some();
+    let has_proofing_profile = color_management.get_proofing_profile_path().is_some();
+    let color_management_menu = Menu::new(LocalizedString::new("Color management"))
+        .entry(
+            MenuItem::new(LocalizedString::new("sRGB monitor"))
+                .selected(!has_monitor_profile)
+                .on_activate(|_ctx, data: &mut AppState, _env| data.set_monitor_profile_path(None)),
+        )
+        .entry(
+            MenuItem::new(LocalizedString::new("Choose monitor profile..."))
+                .selected(has_monitor_profile)
+                .on_activate(|_ctx, data: &mut AppState, _env| data.show_profile_dialog(false)),
+        )
+        .separator()
+        .entry(
+            MenuItem::new(LocalizedString::new("Choose proofing profile..."))
+                .selected(has_proofing_profile)
+                .on_activate(|_ctx, data: &mut AppState, _env| data.show_profile_dialog(true)),
+        )
+        .entry(
+            MenuItem::new(LocalizedString::new("Soft proofing"))
+                .selected(color_management.soft_proofing_enabled())
+                .enabled(has_proofing_profile)
+                .on_activate(|_ctx, data: &mut AppState, _env| data.soft_proofing_enable_toggle()),
+        );
+
+    let recursive_browsing_state = data.recursive_browsing_enabled();
+    let mut depth_limit_menu = Menu::new(LocalizedString::new("Subfolder depth"));
+    for depth_limit in [Some(1), Some(2), Some(3), Some(5), None] {
+        let label = match depth_limit {
+            Some(depth_limit) => depth_limit.to_string(),
+            None => "Unlimited".to_string(),
+        };
+        depth_limit_menu = depth_limit_menu.entry(
+            MenuItem::new(label)
+                .selected(data.folder_depth_limit() == depth_limit)
+                .enabled(recursive_browsing_state)
+                .on_activate(move |_ctx, data: &mut AppState, _env| {
+                    data.set_folder_depth_limit(depth_limit)
+                }),
+        );
+    }
+    let mut sort_menu = Menu::new(LocalizedString::new("Sort by"));
+    for sort_mode in SortMode::ALL {
+        sort_menu = sort_menu.entry(
+            MenuItem::new(LocalizedString::new(sort_mode.label()))
+                .selected(data.sort_mode() == sort_mode)
+                .on_activate(move |_ctx, data: &mut AppState, _env| data.set_sort_mode(sort_mode)),
+        );
+    }
+    let folder_browsing_menu = Menu::new(LocalizedString::new("Folder browsing"))
+        .entry(sort_menu)
+        .entry(
+            MenuItem::new(LocalizedString::new("Include subfolders"))
+                .selected(recursive_browsing_state)
+                .on_activate(|_ctx, data: &mut AppState, _env| {
+                    data.recursive_browsing_enable_toggle()
+                }),
+        )
+        .entry(depth_limit_menu)
+        .entry(
+            MenuItem::new(LocalizedString::new("Skip hidden folders"))
+                .selected(data.skip_hidden_folders())
+                .enabled(recursive_browsing_state)
+                .on_activate(|_ctx, data: &mut AppState, _env| data.skip_hidden_folders_toggle()),
+        );
+
     Menu::empty()
         .entry(MenuItem::new(LocalizedString::new(ABOUT_STR)).enabled(false))
         .separator()
@@ -320,7 +441,33 @@ fn generate_menu(data: &AppState) -> Menu<AppState> {
         )
         .separator()
         .entry(
-            MenuItem::new(LocalizedString::new("Quit"))
-                .on_activate(|_ctx, data: &mut AppState, _env| data.exit()),
+            MenuItem::new(LocalizedString::new("Show pixel grid when zoomed in"))
+                .selected(pixel_grid_state)
+                .enabled(!filtering_state)
+                .on_activate(|_ctx, data: &mut AppState, _env| data.pixel_grid_enable_toggle()),
+        )
+        .entry(tile_grid_menu)
+        .entry(zoom_menu)
+        .entry(background_menu)
+        .entry(color_management_menu)
+        .entry(folder_browsing_menu)
+        .entry(
+            MenuItem::new(LocalizedString::new("Show histogram"))
+                .selected(data.histogram_enabled())
+                .on_activate(|_ctx, data: &mut AppState, _env| data.histogram_enable_toggle()),
+        )
+        .entry(
+            MenuItem::new(LocalizedString::new("Open files in the running window"))
+                .selected(data.single_instance_enabled())
+                .enabled(single_instance::is_supported())
+                .on_activate(|_ctx, data: &mut AppState, _env| {
+                    data.single_instance_enable_toggle()
+                }),
+        )
+        .separator()
+        .entry(MenuItem::new(LocalizedString::new("Preferences…")).command(SHOW_PREFERENCES))
+        .entry(
+            // Closing the window saves its position on the way out
+            MenuItem::new(LocalizedString::new("Quit")).command(CLOSE_WINDOW),
         )
 }
@@ -1,8 +1,11 @@
 use std::ops::*;
 use std::time::Instant;
 
-use druid::Data;
+use druid::{Color, Data};
 use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+
+use crate::image_loader::DecodedImage;
 
 #[derive(Debug, Copy, Clone)]
 pub struct ImageTransformation {
@@ -36,6 +39,15 @@ pub enum Direction {
     Right,
 }
 
+impl Direction {
+    pub fn rotate(&self, image: DynamicImage) -> DynamicImage {
+        match self {
+            Direction::Left => image.rotate270(),
+            Direction::Right => image.rotate90(),
+        }
+    }
+}
+
 #[derive(Clone, Data, Debug)]
 pub enum DisplayState {
     Centered(bool),
@@ -61,6 +73,264 @@ impl DisplayState {
     }
 }
 
+#[derive(Clone, Copy, Data, Debug, PartialEq)]
+pub enum ChannelView {
+    Composite,
+    Red,
+    Green,
+    Blue,
+    Alpha,
+    Luminance,
+}
+
+impl ChannelView {
+    pub const ALL: [ChannelView; 6] = [
+        ChannelView::Composite,
+        ChannelView::Red,
+        ChannelView::Green,
+        ChannelView::Blue,
+        ChannelView::Alpha,
+        ChannelView::Luminance,
+    ];
+
+    pub fn next(&self) -> Self {
+        match self {
+            ChannelView::Composite => ChannelView::Red,
+            ChannelView::Red => ChannelView::Green,
+            ChannelView::Green => ChannelView::Blue,
+            ChannelView::Blue => ChannelView::Alpha,
+            ChannelView::Alpha => ChannelView::Luminance,
+            ChannelView::Luminance => ChannelView::Composite,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ChannelView::Composite => "All channels",
+            ChannelView::Red => "Red channel",
+            ChannelView::Green => "Green channel",
+            ChannelView::Blue => "Blue channel",
+            ChannelView::Alpha => "Alpha channel",
+            ChannelView::Luminance => "Luminance",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Data, Debug, PartialEq)]
+pub enum ToneMapping {
+    Clamp,
+    Reinhard,
+    AcesFilmic,
+}
+
+impl ToneMapping {
+    pub const ALL: [ToneMapping; 3] = [
+        ToneMapping::Clamp,
+        ToneMapping::Reinhard,
+        ToneMapping::AcesFilmic,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ToneMapping::Clamp => "Clamp",
+            ToneMapping::Reinhard => "Reinhard",
+            ToneMapping::AcesFilmic => "ACES filmic",
+        }
+    }
+
+    /// Maps a linear, exposure-adjusted value into the displayable [0, 1] range
+    pub fn apply(&self, value: f32) -> f32 {
+        let value = value.max(0.);
+        let mapped = match self {
+            ToneMapping::Clamp => value,
+            ToneMapping::Reinhard => value / (1. + value),
+            // Krzysztof Narkowicz's fit of the ACES reference rendering transform
+            ToneMapping::AcesFilmic => {
+                (value * (2.51 * value + 0.03)) / (value * (2.43 * value + 0.59) + 0.14)
+            }
+        };
+        mapped.clamp(0., 1.)
+    }
+}
+
+/// Everything that affects how decoded pixel data is turned into the cached display image
+#[derive(Clone, Copy, Data, Debug, PartialEq)]
+pub struct RenderSettings {
+    pub channel_view: ChannelView,
+    pub exposure: f64,
+    pub gamma: f64,
+    pub tone_mapping: ToneMapping,
+    /// Revision of the color management setup the image was converted with
+    pub color_revision: u32,
+}
+
+#[derive(Clone, Data, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum CanvasBackground {
+    Theme,
+    Checkerboard,
+    Solid(Color),
+}
+
+impl CanvasBackground {
+    pub fn to_pref_string(&self) -> String {
+        match self {
+            CanvasBackground::Theme => "theme".to_string(),
+            CanvasBackground::Checkerboard => "checkerboard".to_string(),
+            CanvasBackground::Solid(color) => {
+                let (r, g, b, _a) = color.as_rgba8();
+                format!("#{:02x}{:02x}{:02x}", r, g, b)
+            }
+        }
+    }
+
+    pub fn from_pref_string(pref: &str) -> Option<Self> {
+        match pref {
+            "theme" => Some(CanvasBackground::Theme),
+            "checkerboard" => Some(CanvasBackground::Checkerboard),
+            _ => Color::from_hex_str(pref).ok().map(CanvasBackground::Solid),
+        }
+    }
+}
+
+/// Order of the images found in a folder
+#[derive(Clone, Copy, Data, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum SortMode {
+    Name,
+    Natural,
+    Modified,
+    Size,
+}
+
+impl SortMode {
+    pub const ALL: [SortMode; 4] = [
+        SortMode::Name,
+        SortMode::Natural,
+        SortMode::Modified,
+        SortMode::Size,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SortMode::Name => "Name",
+            SortMode::Natural => "Name, numbers by value",
+            SortMode::Modified => "Date modified",
+            SortMode::Size => "File size",
+        }
+    }
+
+    pub fn to_pref_string(&self) -> String {
+        match self {
+            SortMode::Name => "name",
+            SortMode::Natural => "natural",
+            SortMode::Modified => "modified",
+            SortMode::Size => "size",
+        }
+        .to_string()
+    }
+
+    pub fn from_pref_string(pref: &str) -> Option<Self> {
+        SortMode::ALL
+            .into_iter()
+            .find(|mode| mode.to_pref_string() == pref)
+    }
+}
+
+/// How newly opened images are scaled
+#[derive(Clone, Copy, Data, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ZoomMode {
+    Fit,
+    RealSize,
+    /// A fixed scale, where 1 is the image's actual size
+    Scale(f64),
+}
+
+impl ZoomMode {
+    pub fn to_pref_string(&self) -> String {
+        match self {
+            ZoomMode::Fit => "fit".to_string(),
+            ZoomMode::RealSize => "1:1".to_string(),
+            ZoomMode::Scale(scale) => format!("{}%", scale * 100.),
+        }
+    }
+
+    pub fn from_pref_string(pref: &str) -> Option<Self> {
+        match pref {
+            "fit" => Some(ZoomMode::Fit),
+            "1:1" => Some(ZoomMode::RealSize),
+            _ => {
+                let percentage: f64 = pref.strip_suffix('%').unwrap_or(pref).parse().ok()?;
+                (percentage > 0. && percentage.is_finite())
+                    .then_some(ZoomMode::Scale(percentage / 100.))
+            }
+        }
+    }
+}
+
+/// Whether the interface is light or dark
+#[derive(Clone, Copy, Data, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ThemeMode {
+    /// Follows the desktop, including changes made while the viewer is open
+    System,
+    Light,
+    Dark,
+}
+
+impl ThemeMode {
+    pub const ALL: [ThemeMode; 3] = [ThemeMode::System, ThemeMode::Light, ThemeMode::Dark];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ThemeMode::System => "Follow system",
+            ThemeMode::Light => "Light",
+            ThemeMode::Dark => "Dark",
+        }
+    }
+
+    pub fn to_pref_string(&self) -> String {
+        match self {
+            ThemeMode::System => "system",
+            ThemeMode::Light => "light",
+            ThemeMode::Dark => "dark",
+        }
+        .to_string()
+    }
+
+    pub fn from_pref_string(pref: &str) -> Option<Self> {
+        ThemeMode::ALL
+            .into_iter()
+            .find(|mode| mode.to_pref_string() == pref)
+    }
+}
+
+// Settings store these by their preference strings
+macro_rules! impl_pref_string_conversions {
+    ($type:ty, $expected:literal) => {
+        impl TryFrom<String> for $type {
+            type Error = String;
+
+            fn try_from(pref: String) -> Result<Self, Self::Error> {
+                <$type>::from_pref_string(&pref)
+                    .ok_or_else(|| format!("expected {}, found \"{}\"", $expected, pref))
+            }
+        }
+
+        impl From<$type> for String {
+            fn from(value: $type) -> Self {
+                value.to_pref_string()
+            }
+        }
+    };
+}
+
+impl_pref_string_conversions!(CanvasBackground, "theme, checkerboard or a #rrggbb color");
+impl_pref_string_conversions!(SortMode, "name, natural, modified or size");
+impl_pref_string_conversions!(ZoomMode, "fit, 1:1 or a percentage");
+impl_pref_string_conversions!(ThemeMode, "system, light or dark");
+
 #[derive(Debug, Copy, Clone, Data)]
 pub struct Vec2D<T> {
     pub x: T,
@@ -213,11 +483,11 @@ where
 pub struct NewImageContainer {
     pub path: String,
     pub timestamp: Instant,
-    pub image: DynamicImage,
+    pub image: DecodedImage,
 }
 
 impl NewImageContainer {
-    pub fn from(path: String, timestamp: Instant, image: DynamicImage) -> Self {
+    pub fn from(path: String, timestamp: Instant, image: DecodedImage) -> Self {
         Self {
             path,
             timestamp,
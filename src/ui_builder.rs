@@ -1,23 +1,42 @@
-use druid::{Widget, WindowId};
+use std::path::Path;
+
+use druid::widget::{Button, CrossAxisAlignment, Flex, Label, LineBreaking, Scroll};
+use druid::{ExtEventSink, Widget, WidgetExt, WindowHandle, WindowId};
+#[cfg(windows)]
+use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
 
 use crate::app_state::*;
 use crate::container_widget::*;
 
 #[cfg(windows)]
 mod win_api_calls {
-    use std::ffi::{c_void, OsStr};
+    use std::ffi::{c_void, OsStr, OsString};
     use std::iter::once;
-    use std::os::windows::prelude::OsStrExt;
+    use std::os::windows::prelude::{OsStrExt, OsStringExt};
+    use std::path::Path;
+    use std::path::PathBuf;
+    use std::ptr::{null, null_mut};
+    use std::sync::atomic::{AtomicIsize, Ordering};
+    use std::sync::Mutex;
 
+    use druid::{ExtEventSink, Target};
+
+    use winapi::shared::guiddef::GUID;
     use winapi::shared::minwindef::BOOL;
     use winapi::shared::minwindef::DWORD;
     use winapi::shared::minwindef::FALSE;
     use winapi::shared::minwindef::TRUE;
+    use winapi::shared::minwindef::{LRESULT, UINT};
     use winapi::um::libloaderapi::GetModuleHandleW;
     use winapi::um::processthreadsapi::GetCurrentProcessId;
+    use winapi::um::shellapi::{DragAcceptFiles, DragFinish, DragQueryFileW, HDROP};
+    use winapi::um::unknwnbase::IUnknown;
     use winapi::um::winnt::LPCWSTR;
     use winapi::um::winuser::EnumWindows;
     use winapi::um::winuser::GetWindowThreadProcessId;
+    use winapi::um::winuser::{
+        CallWindowProcW, SetWindowLongPtrW, GWLP_WNDPROC, WM_DROPFILES, WNDPROC,
+    };
     use winapi::{
         shared::minwindef::{LPARAM, LPCVOID, WPARAM},
         shared::ntdef::HRESULT,
@@ -25,6 +44,21 @@ mod win_api_calls {
         um::winuser::{LoadIconW, SendMessageW, ICON_BIG, ICON_SMALL, WM_SETICON},
     };
 
+    use crate::commands::FILES_DROPPED;
+
+    // {0000010e-0000-0000-C000-000000000046}
+    const IID_IDATA_OBJECT: GUID = GUID {
+        Data1: 0x0000010e,
+        Data2: 0x0000,
+        Data3: 0x0000,
+        Data4: [0xC0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46],
+    };
+    const DROPEFFECT_COPY: DWORD = 1;
+
+    static DROP_EVENT_SINK: Mutex<Option<ExtEventSink>> = Mutex::new(None);
+    // The window procedure druid installed, which everything but drops is passed on to
+    static DRUID_WINDOW_PROC: AtomicIsize = AtomicIsize::new(0);
+
     extern "system" {
         pub fn DwmSetWindowAttribute(
             h_wnd: HWND,
@@ -34,6 +68,33 @@ mod win_api_calls {
         ) -> HRESULT;
     }
 
+    #[link(name = "shell32")]
+    extern "system" {
+        fn ILCreateFromPathW(psz_path: LPCWSTR) -> *mut c_void;
+        fn ILFree(pidl: *mut c_void);
+        fn SHCreateDataObject(
+            pidl_folder: *const c_void,
+            cidl: UINT,
+            apidl: *const *const c_void,
+            pdt_inner: *mut c_void,
+            riid: *const GUID,
+            ppv: *mut *mut c_void,
+        ) -> HRESULT;
+        fn SHDoDragDrop(
+            h_wnd: HWND,
+            pdata: *mut c_void,
+            pdsrc: *mut c_void,
+            dw_effect: DWORD,
+            pdw_effect: *mut DWORD,
+        ) -> HRESULT;
+    }
+
+    #[link(name = "ole32")]
+    extern "system" {
+        fn OleInitialize(pv_reserved: *mut c_void) -> HRESULT;
+        fn OleUninitialize();
+    }
+
     pub unsafe fn set_icon() {
         extern "system" fn callback(h_wnd: HWND, _l_param: LPARAM) -> BOOL {
             let mut process_id: DWORD = 0;
@@ -108,12 +169,132 @@ mod win_api_calls {
 
         EnumWindows(Some(callback), 0);
     }
+
+    /// Druid has no drop target, so files dropped from Explorer are caught as WM_DROPFILES
+    pub unsafe fn accept_dropped_files(h_wnd: *mut c_void, event_sink: ExtEventSink) {
+        // Subclassing twice would make drop_window_proc pass messages on to itself
+        if DRUID_WINDOW_PROC.load(Ordering::SeqCst) != 0 {
+            return;
+        }
+        *DROP_EVENT_SINK.lock().unwrap() = Some(event_sink);
+        let h_wnd = h_wnd as HWND;
+        DragAcceptFiles(h_wnd, TRUE);
+        let druid_window_proc =
+            SetWindowLongPtrW(h_wnd, GWLP_WNDPROC, drop_window_proc as usize as _);
+        DRUID_WINDOW_PROC.store(druid_window_proc as isize, Ordering::SeqCst);
+    }
+
+    unsafe extern "system" fn drop_window_proc(
+        h_wnd: HWND,
+        msg: UINT,
+        w_param: WPARAM,
+        l_param: LPARAM,
+    ) -> LRESULT {
+        if msg == WM_DROPFILES {
+            let paths = get_dropped_paths(w_param as HDROP);
+            if let Some(event_sink) = &*DROP_EVENT_SINK.lock().unwrap() {
+                let _result = event_sink.submit_command(FILES_DROPPED, paths, Target::Auto);
+            }
+            return 0;
+        }
+        let druid_window_proc =
+            std::mem::transmute::<isize, WNDPROC>(DRUID_WINDOW_PROC.load(Ordering::SeqCst));
+        CallWindowProcW(druid_window_proc, h_wnd, msg, w_param, l_param)
+    }
+
+    unsafe fn get_dropped_paths(h_drop: HDROP) -> Vec<PathBuf> {
+        let count = DragQueryFileW(h_drop, u32::MAX, null_mut(), 0);
+        let mut paths = Vec::new();
+        for index in 0..count {
+            let length = DragQueryFileW(h_drop, index, null_mut(), 0) as usize;
+            let mut buffer = vec![0u16; length + 1];
+            DragQueryFileW(h_drop, index, buffer.as_mut_ptr(), buffer.len() as UINT);
+            buffer.truncate(length);
+            paths.push(PathBuf::from(OsString::from_wide(&buffer)));
+        }
+        DragFinish(h_drop);
+        paths
+    }
+
+    /// Drags the file as Explorer would, so it can be dropped anywhere that takes files.
+    /// Blocks until the drop, and has to start while the mouse button is still held.
+    pub unsafe fn drag_file(path: &Path) {
+        if OleInitialize(null_mut()) < 0 {
+            println!("Failed to start dragging {}", path.display());
+            return;
+        }
+        let wide_path: Vec<u16> = path.as_os_str().encode_wide().chain(once(0)).collect();
+        let pidl = ILCreateFromPathW(wide_path.as_ptr());
+        if !pidl.is_null() {
+            let pidls = [pidl as *const c_void];
+            let mut data_object: *mut c_void = null_mut();
+            let result = SHCreateDataObject(
+                null(),
+                1,
+                pidls.as_ptr(),
+                null_mut(),
+                &IID_IDATA_OBJECT,
+                &mut data_object,
+            );
+            if result >= 0 {
+                // Without a drop source of our own the shell's default one is used
+                let mut effect: DWORD = 0;
+                SHDoDragDrop(
+                    null_mut(),
+                    data_object,
+                    null_mut(),
+                    DROPEFFECT_COPY,
+                    &mut effect,
+                );
+                (*(data_object as *mut IUnknown)).Release();
+            }
+            ILFree(pidl);
+        }
+        OleUninitialize();
+    }
 }
 
 pub fn build_ui() -> impl Widget<AppState> {
     ContainerWidget::new()
 }
 
+/// Asks before the current image is moved to the trash
+pub fn build_delete_confirmation() -> impl Widget<AppState> {
+    let buttons = Flex::row()
+        .with_child(
+            Button::new("Cancel").on_click(|ctx, _data: &mut AppState, _env| ctx.window().close()),
+        )
+        .with_spacer(8.)
+        .with_child(
+            Button::new("Move to trash").on_click(|ctx, data: &mut AppState, _env| {
+                data.delete_image();
+                ctx.window().close();
+            }),
+        );
+    Flex::column()
+        .with_child(Label::dynamic(|data: &AppState, _env| {
+            format!("Move {} to the trash?", data.get_image_name())
+        }))
+        .with_spacer(16.)
+        .with_child(buttons)
+        .padding(16.)
+}
+
+/// Lists what went wrong loading the settings file, which would otherwise only be printed
+pub fn build_settings_errors(errors: Vec<String>) -> impl Widget<AppState> {
+    let details = Label::new(errors.join("\n")).with_line_break_mode(LineBreaking::WordWrap);
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(Label::new("Some settings couldn't be loaded:"))
+        .with_spacer(8.)
+        .with_flex_child(Scroll::new(details).vertical(), 1.)
+        .with_spacer(16.)
+        .with_child(
+            Button::new("OK").on_click(|ctx, _data: &mut AppState, _env| ctx.window().close()),
+        )
+        .padding(16.)
+}
+
 #[cfg(windows)]
 pub fn platform_api_calls(_id: WindowId) {
     unsafe {
@@ -124,3 +305,38 @@ pub fn platform_api_calls(_id: WindowId) {
 
 #[cfg(not(windows))]
 pub fn platform_api_calls(_id: WindowId) {}
+
+/// Lets files and folders be dropped onto the given window, which then sends FILES_DROPPED
+#[cfg(windows)]
+pub fn accept_dropped_files(handle: &WindowHandle, event_sink: ExtEventSink) {
+    if let RawWindowHandle::Win32(window_handle) = handle.raw_window_handle() {
+        unsafe {
+            win_api_calls::accept_dropped_files(window_handle.hwnd, event_sink);
+        }
+    }
+}
+
+// Druid doesn't report drops from the desktop on other platforms yet
+#[cfg(not(windows))]
+pub fn accept_dropped_files(_handle: &WindowHandle, _event_sink: ExtEventSink) {}
+
+/// Starts dragging a file out of the viewer into other applications
+#[cfg(windows)]
+pub fn drag_file_out(path: &Path) {
+    unsafe {
+        win_api_calls::drag_file(path);
+    }
+}
+
+// Druid can't start a drag on other platforms yet
+#[cfg(not(windows))]
+pub fn drag_file_out(path: &Path) {
+    println!(
+        "Dragging {} out isn't supported on this platform",
+        path.display()
+    );
+}
+
+pub fn is_drag_out_supported() -> bool {
+    cfg!(windows)
+}
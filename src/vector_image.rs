@@ -0,0 +1,65 @@
+use std::error::Error;
+use std::sync::{Arc, OnceLock};
+
+use druid::Rect;
+use image::{DynamicImage, RgbaImage};
+
+use crate::image_loader::DecodedImage;
+
+// Loading system fonts is slow, so the parser options are only built once
+static SVG_OPTIONS: OnceLock<usvg::Options> = OnceLock::new();
+
+fn get_svg_options() -> &'static usvg::Options {
+    SVG_OPTIONS.get_or_init(|| {
+        let mut options = usvg::Options::default();
+        options.fontdb.load_system_fonts();
+        options
+    })
+}
+
+/// Decodes an SVG or SVGZ document. The bitmap rendition at its natural size stands in for the
+/// image everywhere a bitmap is needed, while the source is kept for sharp rendering at any zoom.
+pub fn decode_svg(data: &[u8]) -> Result<DecodedImage, Box<dyn Error>> {
+    let tree = usvg::Tree::from_data(data, &get_svg_options().to_ref())?;
+    let document_size = tree.svg_node().size;
+    let region = Rect::new(0., 0., document_size.width(), document_size.height());
+    let rgba = render_tree(&tree, region, 1.).ok_or("Failed to render SVG document")?;
+
+    let mut decoded_image = DecodedImage::from_image(DynamicImage::ImageRgba8(rgba));
+    decoded_image.vector_source = Some(Arc::new(data.to_vec()));
+    Ok(decoded_image)
+}
+
+/// Parses the document kept for sharp rendering. The tree can't leave the thread it was made
+/// on, so it's parsed where it's drawn rather than along with the bitmap rendition.
+pub fn parse_svg(data: &[u8]) -> Option<usvg::Tree> {
+    usvg::Tree::from_data(data, &get_svg_options().to_ref()).ok()
+}
+
+/// Renders the given region of the document, in document units, at the given scale
+pub fn rasterize_svg_region(tree: &usvg::Tree, region: Rect, scale: f64) -> Option<RgbaImage> {
+    render_tree(tree, region, scale)
+}
+
+fn render_tree(tree: &usvg::Tree, region: Rect, scale: f64) -> Option<RgbaImage> {
+    let width = (region.width() * scale).ceil().max(1.) as u32;
+    let height = (region.height() * scale).ceil().max(1.) as u32;
+    let mut pixmap = tiny_skia::Pixmap::new(width, height)?;
+    let transform = tiny_skia::Transform::from_row(
+        scale as f32,
+        0.,
+        0.,
+        scale as f32,
+        (-region.x0 * scale) as f32,
+        (-region.y0 * scale) as f32,
+    );
+    resvg::render(tree, usvg::FitTo::Original, transform, pixmap.as_mut())?;
+
+    // tiny-skia works with premultiplied alpha, while the rest of the pipeline doesn't
+    let mut rgba = RgbaImage::new(width, height);
+    for (pixel, source) in rgba.pixels_mut().zip(pixmap.pixels()) {
+        let color = source.demultiply();
+        pixel.0 = [color.red(), color.green(), color.blue(), color.alpha()];
+    }
+    Some(rgba)
+}